@@ -0,0 +1,503 @@
+mod output;
+
+use std::{collections::HashSet, fs, io::{self, Write}, path::{Path, PathBuf}, process::ExitCode};
+use clap::{Parser, Subcommand, ValueEnum};
+use chrono::NaiveDate;
+use chance_encounters_core::{cleaning::{find_outliers, without_outliers, SpeedLimits}, compute::*, decoders::{binary::BinaryRecord, bundle::{Bundle, KdfParams}, DecoderRegistry}, exclusion::ExclusionZone, export::{self, EncounterRow}, filter::{Area, PointFilter}, model::SpaceTimeRecord, places::{frequent_places, place_at, Place}, redaction::{redact, DateRange, RedactionOptions}, simplify::{simplify, SimplifyOptions}, tokens::{CellTokens, TokenKey, TokenOptions, TokenReply, TokenSet}};
+
+const EXIT_READ_ERROR: u8 = 1;
+const EXIT_DECODE_ERROR: u8 = 3;
+const EXIT_WRITE_ERROR: u8 = 4;
+
+/// Find when and where people were near each other from their location histories
+#[derive(Parser)]
+#[command(name = "chance-encounters", version, about, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true, after_help = "Exit codes: 0 success, 1 a file could not be read, 2 invalid arguments, 3 a file could not be decoded, 4 results or records could not be written")]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Location history files, every pair of files is compared
+    #[arg(required = true, num_args = 2..)]
+    files: Vec<PathBuf>,
+    /// Furthest apart two people can be, in meters
+    #[arg(long, default_value_t = EncounterOptions::default().max_distance_km * 1000.0)]
+    max_distance: f64,
+    /// Longest time between two points that still counts as being there at the same time, in seconds
+    #[arg(long, default_value_t = EncounterOptions::default().max_time_gap_secs)]
+    max_time_gap: i64,
+    /// Matches closer together than this are merged into a single encounter, in seconds
+    #[arg(long, default_value_t = EncounterOptions::default().episode_gap_secs)]
+    episode_gap: i64,
+    /// Only look at points from this date on, as YYYY-MM-DD
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Only look at points up to and including this date, as YYYY-MM-DD
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    /// Only look at points inside a bounding box "min_lat,min_lon,max_lat,max_lon" or polygon "lat,lon;lat,lon;lat,lon..."
+    #[arg(long, allow_hyphen_values = true)]
+    area: Option<Area>,
+    /// Only keep encounters at, or away from, either person's frequent places such as their home or work
+    #[arg(long, value_enum)]
+    places: Option<PlaceFilter>,
+    /// Drop, or only list, points that would mean moving impossibly fast for the activity, such as far off cell tower fixes
+    #[arg(long, value_enum)]
+    outliers: Option<OutlierHandling>,
+    /// Collapse stays into single points and thin out movement before matching, keeping every point within this many meters
+    /// of the point that replaces it. Makes matching dense histories faster, try 20
+    #[arg(long)]
+    simplify: Option<f64>,
+    /// Passphrase for any encrypted bundles among the files
+    #[arg(long)]
+    passphrase: Option<String>,
+    /// Also save each decoded history to this directory in the compact binary format, which loads faster and can be shared
+    #[arg(long)]
+    save_records: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+}
+
+/// Finding encounters without sharing location histories, by each person sharing blinded tokens of the places and times they were in
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt a location history with a passphrase, so it can be sent to someone without anyone else being able to read it
+    Bundle {
+        file: PathBuf,
+        /// Needed to open the bundle, share it some other way than the bundle itself
+        #[arg(long)]
+        passphrase: String,
+        /// Name to show for the person, the file name when not given
+        #[arg(long)]
+        name: Option<String>,
+        /// Where to write the bundle, the file name with a .ceb extension when not given
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Remove or blur sensitive places and times in a location history before sharing it
+    Redact {
+        file: PathBuf,
+        /// Drop points inside a zone "lat,lon" or "lat,lon,radius_m", can be given more than once
+        #[arg(long, allow_hyphen_values = true)]
+        exclude: Vec<ExclusionZone>,
+        /// Drop points on a day "YYYY-MM-DD" or days "YYYY-MM-DD..YYYY-MM-DD", can be given more than once
+        #[arg(long)]
+        drop_dates: Vec<DateRange>,
+        /// Move coordinates to the centre of a grid of cells this size, in meters
+        #[arg(long)]
+        grid: Option<f64>,
+        /// Widen times out to whole buckets this long, in minutes
+        #[arg(long)]
+        time_bucket: Option<i64>,
+        /// Encrypt the result as a bundle with this passphrase when it's saved as a record, it's saved as a binary
+        /// record otherwise. Also opens the file if it's a bundle
+        #[arg(long)]
+        passphrase: Option<String>,
+        /// Save the result as a record that can be opened again, or export its points
+        #[arg(long, value_enum, default_value_t = RecordFormat::Record)]
+        format: RecordFormat,
+        /// Where to write the result, the file name with a .redacted extension for the format when not given
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Turn a location history into tokens that can be shared instead of it, the first step of a comparison
+    Tokens {
+        #[command(flatten)]
+        tokens: TokenArgs,
+        /// Where to write the tokens, printed when not given
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Blind someone else's tokens with your own secret to send back to them, the second step of a comparison
+    ReplyTokens {
+        /// Tokens made by the other person
+        other: PathBuf,
+        /// The same secret your own tokens were made with
+        #[arg(long)]
+        secret: String,
+        /// Where to write the reply, printed when not given
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Compare a location history with someone else's tokens and their reply to yours, listing the times and places
+    /// you might have met
+    MatchTokens {
+        #[command(flatten)]
+        tokens: TokenArgs,
+        /// Tokens made by the other person
+        other: PathBuf,
+        /// The other person's reply to your tokens
+        reply: PathBuf,
+    },
+}
+
+#[derive(clap::Args)]
+struct TokenArgs {
+    /// Your own location history
+    file: PathBuf,
+    /// Your own secret, never shared. Use the same one for every step of a comparison and a new one for each comparison
+    #[arg(long)]
+    secret: String,
+    /// Size of the grid cells, both people must use the same size
+    #[arg(long, default_value_t = TokenOptions::default().cell_size_m)]
+    cell_size: f64,
+    /// Length of the time buckets in seconds, both people must use the same length
+    #[arg(long, default_value_t = TokenOptions::default().bucket_secs)]
+    bucket: i64,
+    /// Passphrase for the history if it's an encrypted bundle
+    #[arg(long)]
+    passphrase: Option<String>,
+}
+
+impl TokenArgs {
+    fn cell_tokens(&self) -> Result<CellTokens, ExitCode> {
+        let person = decode_files(std::slice::from_ref(&self.file), &PointFilter::default(), self.passphrase.as_deref())?.remove(0);
+        let options = TokenOptions { cell_size_m: self.cell_size, bucket_secs: self.bucket, ..TokenOptions::default() };
+        Ok(CellTokens::new(&person.record, &token_key(&self.secret)?, options))
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+    Geojson,
+    Ics,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RecordFormat {
+    /// A binary record, or a bundle when there's a passphrase
+    Record,
+    Json,
+    Csv,
+    Geojson,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutlierHandling {
+    Drop,
+    Flag,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum PlaceFilter {
+    At,
+    Away,
+}
+
+/// How many of each person's frequent places are looked for
+const MAX_FREQUENT_PLACES: usize = 5;
+
+struct Person {
+    name: String,
+    /// The format the history was first decoded from
+    source_format: String,
+    record: SpaceTimeRecord,
+    places: Vec<Place>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Bundle { file, passphrase, name, output }) => return write_bundle(file, passphrase, name.as_deref(), output.as_deref()),
+        Some(Command::Redact { file, exclude, drop_dates, grid, time_bucket, passphrase, format, output }) => {
+            let options = RedactionOptions { zones: exclude.clone(), dates: drop_dates.clone(), grid_m: *grid, time_bucket_mins: *time_bucket };
+            return write_redacted(file, &options, passphrase.as_deref(), *format, output.as_deref());
+        },
+        Some(Command::Tokens { tokens, output }) => return write_tokens(tokens, output.as_deref()),
+        Some(Command::ReplyTokens { other, secret, output }) => return write_reply(other, secret, output.as_deref()),
+        Some(Command::MatchTokens { tokens, other, reply }) => return match_tokens(tokens, other, reply),
+        None => (),
+    }
+    let options = EncounterOptions {
+        max_distance_km: args.max_distance / 1000.0,
+        max_time_gap_secs: args.max_time_gap,
+        episode_gap_secs: args.episode_gap,
+    };
+
+    let filter = PointFilter::from_dates(args.from, args.to, args.area);
+
+    let mut people = match decode_files(&args.files, &filter, args.passphrase.as_deref()) {
+        Ok(people) => people,
+        Err(exit_code) => return exit_code,
+    };
+    if let Some(handling) = args.outliers {
+        for person in &mut people {
+            let outliers = match handling {
+                OutlierHandling::Drop => {
+                    let (record, outliers) = without_outliers(&person.record, &SpeedLimits::default());
+                    person.record = record;
+                    outliers
+                },
+                OutlierHandling::Flag => find_outliers(&person.record, &SpeedLimits::default()),
+            };
+            for outlier in &outliers {
+                eprintln!("{}: outlier at {} {:.5}, {:.5}, {:.0} km/h where at most {:.0} km/h is believable", person.name, outlier.start_time.to_rfc3339(), outlier.latitude, outlier.longitude, outlier.speed_kmh, outlier.max_speed_kmh);
+            }
+            let verb = match handling {
+                OutlierHandling::Drop => "removed",
+                OutlierHandling::Flag => "found",
+            };
+            eprintln!("{}: {} outliers {}", person.name, outliers.len(), verb);
+        }
+    }
+    if let Some(tolerance_m) = args.simplify {
+        let simplify_options = SimplifyOptions { tolerance_m, ..SimplifyOptions::default() };
+        for person in &mut people {
+            let (record, report) = simplify(&person.record, &simplify_options);
+            eprintln!("{}: simplified from {} to {} points, {} stays", person.name, report.points_before, report.points_after, report.stays);
+            person.record = record;
+        }
+    }
+    if let Some(directory) = &args.save_records {
+        for (person, file_name) in people.iter().zip(record_file_names(&people)) {
+            let path = directory.join(file_name);
+            if let Err(err) = fs::write(&path, BinaryRecord::encode(&person.record, &person.name, &person.source_format)) {
+                eprintln!("{}: {}", path.display(), err);
+                return ExitCode::from(EXIT_WRITE_ERROR);
+            }
+        }
+    }
+    if args.places.is_some() {
+        for person in &mut people {
+            person.places = frequent_places(&person.record, MAX_FREQUENT_PLACES);
+            for place in &person.places {
+                eprintln!("{}: {} at {:.5}, {:.5}, {}h over {} visits", person.name, place.kind, place.latitude, place.longitude, place.dwell_secs / 3600, place.visits);
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (i, person1) in people.iter().enumerate() {
+        for person2 in &people[i + 1..] {
+            for encounter in analyze(&person1.record, &person2.record, &options) {
+                if let Some(place_filter) = args.places {
+                    let at_place = [&person1.places, &person2.places].into_iter().any(|places| place_at(places, encounter.latitude, encounter.longitude).is_some());
+                    if at_place != (place_filter == PlaceFilter::At) {
+                        continue;
+                    }
+                }
+                let (start_time, end_time) = (encounter.start_time, encounter.end_time);
+                rows.push(EncounterRow {
+                    person1: &person1.name,
+                    person2: &person2.name,
+                    encounter,
+                    track1: person1.record.points_between(start_time, end_time),
+                    track2: person2.record.points_between(start_time, end_time),
+                });
+            }
+        }
+    }
+
+    let mut stdout = io::stdout().lock();
+    let written = match args.format {
+        OutputFormat::Table => output::write_table(&mut stdout, &rows),
+        OutputFormat::Json => export::write_json(&mut stdout, &rows),
+        OutputFormat::Csv => export::write_csv(&mut stdout, &rows),
+        OutputFormat::Geojson => export::write_geojson(&mut stdout, &rows),
+        OutputFormat::Ics => export::write_ics(&mut stdout, &rows),
+    };
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Unable to write results: {}", err);
+            ExitCode::from(EXIT_WRITE_ERROR)
+        }
+    }
+}
+
+/// Each person's saved record file, numbered when two share a name so neither overwrites the other, like
+/// `a/history.json` and `b/history.json` or two bundles of the same person
+fn record_file_names(people: &[Person]) -> Vec<String> {
+    let mut taken = HashSet::new();
+    people.iter().map(|person| {
+        (1..).map(|n| match n {
+            1 => format!("{}.{}", person.name, BinaryRecord::EXTENSION),
+            n => format!("{}-{}.{}", person.name, n, BinaryRecord::EXTENSION),
+        }).find(|file_name| taken.insert(file_name.clone())).unwrap_or_default()
+    }).collect()
+}
+
+fn write_bundle(file: &PathBuf, passphrase: &str, name: Option<&str>, output: Option<&Path>) -> ExitCode {
+    let person = match decode_files(std::slice::from_ref(file), &PointFilter::default(), None) {
+        Ok(mut people) => people.remove(0),
+        Err(exit_code) => return exit_code,
+    };
+    let output = output.map(PathBuf::from).unwrap_or_else(|| file.with_extension(Bundle::EXTENSION));
+    save_record(&person.record, name.unwrap_or(&person.name), &person.source_format, Some(passphrase), &output)
+}
+
+fn write_redacted(file: &PathBuf, options: &RedactionOptions, passphrase: Option<&str>, format: RecordFormat, output: Option<&Path>) -> ExitCode {
+    let person = match decode_files(std::slice::from_ref(file), &PointFilter::default(), passphrase) {
+        Ok(mut people) => people.remove(0),
+        Err(exit_code) => return exit_code,
+    };
+    let (record, report) = redact(&person.record, options);
+    eprintln!("{}: {}", file.display(), report);
+    let extension = match (format, passphrase) {
+        (RecordFormat::Record, Some(_)) => Bundle::EXTENSION,
+        (RecordFormat::Record, None) => BinaryRecord::EXTENSION,
+        (RecordFormat::Json, _) => "json",
+        (RecordFormat::Csv, _) => "csv",
+        (RecordFormat::Geojson, _) => "geojson",
+    };
+    let output = output.map(PathBuf::from).unwrap_or_else(|| file.with_extension(format!("redacted.{}", extension)));
+
+    let mut content = Vec::new();
+    let written = match format {
+        RecordFormat::Record => return save_record(&record, &person.name, &person.source_format, passphrase, &output),
+        RecordFormat::Json => export::write_points_json(&mut content, &record.points),
+        RecordFormat::Csv => export::write_points_csv(&mut content, &record.points),
+        RecordFormat::Geojson => export::write_points_geojson(&mut content, &person.name, &record.points),
+    };
+    match written.and_then(|_| fs::write(&output, content)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {}", output.display(), err);
+            ExitCode::from(EXIT_WRITE_ERROR)
+        }
+    }
+}
+
+/// Writes a record as an encrypted bundle when there's a passphrase, or as a binary record
+fn save_record(record: &SpaceTimeRecord, name: &str, source_format: &str, passphrase: Option<&str>, output: &Path) -> ExitCode {
+    let content = match passphrase {
+        Some(passphrase) => {
+            let mut salt = [0; Bundle::SALT_LENGTH];
+            getrandom::getrandom(&mut salt).map_err(|err| err.to_string())
+                .and_then(|_| Bundle::seal(record, name, source_format, passphrase, salt, KdfParams::default()).map_err(|err| err.to_string()))
+        },
+        None => Ok(BinaryRecord::encode(record, name, source_format)),
+    };
+    match content.and_then(|content| fs::write(output, content).map_err(|err| err.to_string())) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {}", output.display(), err);
+            ExitCode::from(EXIT_WRITE_ERROR)
+        }
+    }
+}
+
+fn token_key(secret: &str) -> Result<TokenKey, ExitCode> {
+    TokenKey::from_secret(secret, KdfParams::default()).map_err(|err| {
+        eprintln!("{}", err);
+        ExitCode::from(EXIT_DECODE_ERROR)
+    })
+}
+
+fn write_tokens(args: &TokenArgs, output: Option<&Path>) -> ExitCode {
+    let cell_tokens = match args.cell_tokens() {
+        Ok(cell_tokens) => cell_tokens,
+        Err(exit_code) => return exit_code,
+    };
+    eprintln!("{} tokens", cell_tokens.len());
+    write_json(serde_json::to_vec(&cell_tokens.export()), output)
+}
+
+fn write_reply(other: &Path, secret: &str, output: Option<&Path>) -> ExitCode {
+    let reply = read_token_file(other, |content| serde_json::from_slice::<TokenSet>(content))
+        .and_then(|other_tokens| token_key(secret)?.reply(&other_tokens).map_err(|err| {
+            eprintln!("{}: {}", other.display(), err);
+            ExitCode::from(EXIT_DECODE_ERROR)
+        }));
+    match reply {
+        Ok(reply) => write_json(serde_json::to_vec(&reply), output),
+        Err(exit_code) => exit_code,
+    }
+}
+
+/// Writes tokens or a reply to a file, or prints them
+fn write_json(content: serde_json::Result<Vec<u8>>, output: Option<&Path>) -> ExitCode {
+    let written = content.map_err(io::Error::from).and_then(|content| match output {
+        Some(path) => fs::write(path, content),
+        None => io::stdout().lock().write_all(&content),
+    });
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Unable to write tokens: {}", err);
+            ExitCode::from(EXIT_WRITE_ERROR)
+        }
+    }
+}
+
+fn read_token_file<T>(path: &Path, parse: impl Fn(&[u8]) -> serde_json::Result<T>) -> Result<T, ExitCode> {
+    let content = fs::read(path).map_err(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        ExitCode::from(EXIT_READ_ERROR)
+    })?;
+    parse(&content).map_err(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        ExitCode::from(EXIT_DECODE_ERROR)
+    })
+}
+
+fn match_tokens(args: &TokenArgs, other: &Path, reply: &Path) -> ExitCode {
+    let other_tokens = match read_token_file(other, |content| serde_json::from_slice::<TokenSet>(content)) {
+        Ok(other_tokens) => other_tokens,
+        Err(exit_code) => return exit_code,
+    };
+    let reply_tokens = match read_token_file(reply, |content| serde_json::from_slice::<TokenReply>(content)) {
+        Ok(reply_tokens) => reply_tokens,
+        Err(exit_code) => return exit_code,
+    };
+    let cell_tokens = match args.cell_tokens() {
+        Ok(cell_tokens) => cell_tokens,
+        Err(exit_code) => return exit_code,
+    };
+    let candidates = match cell_tokens.candidates(&other_tokens, &reply_tokens) {
+        Ok(candidates) => candidates,
+        Err(err) => {
+            eprintln!("{}: {}", other.display(), err);
+            return ExitCode::from(EXIT_DECODE_ERROR);
+        }
+    };
+    match output::write_candidates(&mut io::stdout().lock(), &candidates) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Unable to write results: {}", err);
+            ExitCode::from(EXIT_WRITE_ERROR)
+        }
+    }
+}
+
+fn decode_files(paths: &[PathBuf], filter: &PointFilter, passphrase: Option<&str>) -> Result<Vec<Person>, ExitCode> {
+    let decoders = DecoderRegistry::default();
+    let mut people = Vec::with_capacity(paths.len());
+    for path in paths {
+        let content = fs::read(path).map_err(|err| {
+            eprintln!("{}: {}", path.display(), err);
+            ExitCode::from(EXIT_READ_ERROR)
+        })?;
+        // Bundles are opened first so they're read like the saved record inside, along with its person's name
+        let content = match passphrase {
+            Some(passphrase) if Bundle::is_bundle(&content) => Bundle::open(&content, passphrase).map_err(|err| {
+                eprintln!("{}: {}", path.display(), err);
+                ExitCode::from(EXIT_DECODE_ERROR)
+            })?,
+            _ => content,
+        };
+        let decoder = decoders.detect(&content).map_err(|err| {
+            eprintln!("{}: {}", path.display(), err);
+            ExitCode::from(EXIT_DECODE_ERROR)
+        })?;
+        let record = decoder.decode_filtered(&content, filter).map_err(|err| {
+            eprintln!("{}: {}", path.display(), err);
+            ExitCode::from(EXIT_DECODE_ERROR)
+        })?;
+        for warning in &record.warnings {
+            eprintln!("{}: warning: {}", path.display(), warning);
+        }
+        eprintln!("{}: {} points", path.display(), record.points.len());
+
+        let name = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+        // Saved records carry the person's name and the format they were first decoded from
+        let (name, source_format) = match BinaryRecord::read_header(&content) {
+            Ok(header) if header.person.is_empty() => (name, header.source_format),
+            Ok(header) => (header.person, header.source_format),
+            Err(_) => (name, decoder.name().to_string()),
+        };
+        people.push(Person { name, source_format, record, places: Vec::new() });
+    }
+    Ok(people)
+}
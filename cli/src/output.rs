@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+use chance_encounters_core::{export::{format_duration, EncounterRow}, timezone::utc_offset_at, tokens::CandidateEncounter};
+
+/// Local time where the encounter happened, with its offset from UTC
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+pub fn write_table(out: &mut impl Write, rows: &[EncounterRow]) -> io::Result<()> {
+    if rows.is_empty() {
+        return writeln!(out, "No encounters found");
+    }
+    let name_width = rows.iter()
+        .flat_map(|row| [row.person1.len(), row.person2.len()])
+        .chain(["Person 1".len()])
+        .max()
+        .unwrap_or_default();
+
+    writeln!(out, "{:<name_width$}  {:<name_width$}  {:<26}  {:>9}  {:>8}  {:>10}  {:>11}  {:>5}", "Person 1", "Person 2", "Start (local)", "Duration", "Distance", "Latitude", "Longitude", "Score")?;
+    for row in rows {
+        writeln!(out, "{:<name_width$}  {:<name_width$}  {:<26}  {:>9}  {:>7.0}m  {:>10.5}  {:>11.5}  {:>5.0}",
+            row.person1,
+            row.person2,
+            row.encounter.start_time.with_timezone(&row.encounter.utc_offset()).format(TIME_FORMAT),
+            format_duration(row.encounter.duration()),
+            row.encounter.min_distance_km * 1000.0,
+            row.encounter.latitude,
+            row.encounter.longitude,
+            row.encounter.score)?;
+    }
+    writeln!(out, "{} encounters", rows.len())
+}
+
+/// Candidates are only as precise as the token cells and buckets, so they're shown coarsely
+pub fn write_candidates(out: &mut impl Write, candidates: &[CandidateEncounter]) -> io::Result<()> {
+    if candidates.is_empty() {
+        return writeln!(out, "No possible encounters found");
+    }
+    writeln!(out, "{:<26}  {:>9}  {:>9}  {:>10}", "Start (local)", "Duration", "Latitude", "Longitude")?;
+    for candidate in candidates {
+        let offset = utc_offset_at(candidate.latitude, candidate.longitude, candidate.start_time);
+        writeln!(out, "{:<26}  {:>9}  {:>9.3}  {:>10.3}",
+            candidate.start_time.with_timezone(&offset).format(TIME_FORMAT),
+            format_duration(candidate.end_time - candidate.start_time),
+            candidate.latitude,
+            candidate.longitude)?;
+    }
+    writeln!(out, "{} possible encounters", candidates.len())
+}
@@ -0,0 +1,98 @@
+//! Keeping decoded records around between analyses so changing a threshold doesn't mean decoding again
+
+use std::sync::{Arc, OnceLock};
+use crate::{compute::SpaceTimeIndex, model::SpaceTimeRecord};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a hash of a file's content, used to recognise a file that has already been decoded
+pub fn content_hash(content: &[u8]) -> u64 {
+    content.iter().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// A decoded record along with its index, which is only built the first time it's needed
+pub struct CachedRecord {
+    pub record: SpaceTimeRecord,
+    index: OnceLock<SpaceTimeIndex>,
+}
+
+impl CachedRecord {
+    pub fn new(record: SpaceTimeRecord) -> Self {
+        CachedRecord { record, index: OnceLock::new() }
+    }
+
+    pub fn index(&self) -> &SpaceTimeIndex {
+        self.index.get_or_init(|| SpaceTimeIndex::new(&self.record))
+    }
+}
+
+/// The most recently used records, keyed by content hash
+pub struct RecordCache {
+    capacity: usize,
+    /// Least recently used first
+    entries: Vec<(u64, Arc<CachedRecord>)>,
+}
+
+impl RecordCache {
+    pub fn new(capacity: usize) -> Self {
+        RecordCache { capacity, entries: Vec::with_capacity(capacity) }
+    }
+
+    pub fn get(&mut self, hash: u64) -> Option<Arc<CachedRecord>> {
+        let position = self.entries.iter().position(|(entry_hash, _)| *entry_hash == hash)?;
+        let entry = self.entries.remove(position);
+        let record = entry.1.clone();
+        self.entries.push(entry);
+        Some(record)
+    }
+
+    /// Add a record, dropping the least recently used one if the cache is full
+    pub fn insert(&mut self, hash: u64, record: SpaceTimeRecord) -> Arc<CachedRecord> {
+        self.entries.retain(|(entry_hash, _)| *entry_hash != hash);
+        if self.entries.len() >= self.capacity.max(1) {
+            self.entries.remove(0);
+        }
+        let cached = Arc::new(CachedRecord::new(record));
+        self.entries.push((hash, cached.clone()));
+        cached
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(content_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(content_hash(b"ab"), content_hash(b"ba"));
+    }
+
+    #[test]
+    fn test_record_cache() {
+        let record = || SpaceTimeRecord::new(Vec::new(), Vec::new());
+        let mut cache = RecordCache::new(2);
+        cache.insert(1, record());
+        cache.insert(2, record());
+        assert!(cache.get(1).is_some());
+        // 2 is now the least recently used
+        cache.insert(3, record());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(3).is_some());
+
+        let cached = cache.get(1).unwrap();
+        assert!(std::ptr::eq(cached.index(), cache.get(1).unwrap().index()));
+    }
+}
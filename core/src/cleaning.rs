@@ -0,0 +1,275 @@
+//! Finding points that would mean moving impossibly fast, like a cell tower fix hundreds of kilometers from the rest
+//! of the track, so they don't show up as encounters somewhere the person never was
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::model::{Activity, SpaceTimePoint, SpaceTimeRecord};
+
+/// Jumps shorter than this are never outliers, they're GPS jitter rather than a wrong fix
+const MIN_JUMP_M: f64 = 100.0;
+/// Longest a run of points away from the rest of the track can last and still be outliers, anything longer is
+/// somewhere the person really went
+const MAX_OUTLIER_RUN_SECS: i64 = 30 * 60;
+
+/// Fastest believable speed for each activity in km/h, generous so real journeys are never cut
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpeedLimits {
+    pub stationary: f64,
+    pub walking: f64,
+    pub running: f64,
+    pub cycling: f64,
+    pub driving: f64,
+    pub transit: f64,
+    pub flying: f64,
+    /// For points without an activity, about as fast as a high speed train
+    pub unknown: f64,
+}
+
+impl Default for SpeedLimits {
+    fn default() -> Self {
+        SpeedLimits {
+            stationary: 15.0,
+            walking: 15.0,
+            running: 30.0,
+            cycling: 60.0,
+            driving: 200.0,
+            transit: 350.0,
+            flying: 1200.0,
+            unknown: 300.0,
+        }
+    }
+}
+
+impl SpeedLimits {
+    pub fn for_activity(&self, activity: Option<Activity>) -> f64 {
+        match activity {
+            Some(Activity::Stationary) => self.stationary,
+            Some(Activity::Walking) => self.walking,
+            Some(Activity::Running) => self.running,
+            Some(Activity::Cycling) => self.cycling,
+            Some(Activity::Driving) => self.driving,
+            Some(Activity::Transit) => self.transit,
+            Some(Activity::Flying) => self.flying,
+            None => self.unknown,
+        }
+    }
+
+    /// Between two points the faster of their activities is allowed, the person may have changed in between
+    fn between(&self, from: &SpaceTimePoint, to: &SpaceTimePoint) -> f64 {
+        self.for_activity(from.activity).max(self.for_activity(to.activity))
+    }
+}
+
+/// A point that can't be reached from the one before at a believable speed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Outlier {
+    /// Position of the point in the record it was found in
+    pub index: usize,
+    pub start_time: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub speed_kmh: f64,
+    pub max_speed_kmh: f64,
+}
+
+/// Speed needed to get from one point to the next, in km/h. The points' accuracy is taken off the distance, and
+/// the time runs from the end of the first point when there's a gap after it, otherwise from its start as it
+/// lasts until the next sample
+pub fn implied_speed_kmh(from: &SpaceTimePoint, to: &SpaceTimePoint) -> f64 {
+    let distance_m = from.haversine_distance(to.latitude, to.longitude) * 1000.0;
+    if distance_m < MIN_JUMP_M {
+        return 0.0;
+    }
+    let distance_m = (distance_m - from.accuracy.unwrap_or_default() - to.accuracy.unwrap_or_default()).max(0.0);
+    let departure = match from.end_time < to.start_time {
+        true => from.end_time,
+        false => from.start_time,
+    };
+    let seconds = ((to.start_time - departure).num_milliseconds() as f64 / 1000.0).max(1.0);
+    distance_m / 1000.0 / (seconds / 3600.0)
+}
+
+/// Points in brief runs that jump away from the track too fast for the activity. The track is split into runs at
+/// each jump, and a run lasting at most `MAX_OUTLIER_RUN_SECS` is taken out when the runs either side of it can be
+/// reached from each other, so the track comes back to where it was. A jump the track doesn't come back from is a
+/// real journey, such as a flight with no points along the way. At the start and end of the record there's nothing
+/// on the other side to check, so a brief run there is taken out when it jumps away from a run with more points and
+/// the track never comes back to it.
+/// Removing a run can join its neighbours, so this repeats until nothing else is found
+pub fn find_outliers(record: &SpaceTimeRecord, limits: &SpeedLimits) -> Vec<Outlier> {
+    let points = &record.points;
+    let reachable = |from: &SpaceTimePoint, to: &SpaceTimePoint| implied_speed_kmh(from, to) <= limits.between(from, to);
+    let mut kept: Vec<usize> = (0..points.len()).collect();
+    // Whether each point has been found, so dropping them doesn't search the outliers for every point
+    let mut removed = vec![false; points.len()];
+    let mut outliers = Vec::new();
+    loop {
+        let runs: Vec<&[usize]> = kept.chunk_by(|from, to| reachable(&points[*from], &points[*to])).collect();
+        let mut found = Vec::new();
+        for (position, run) in runs.iter().enumerate() {
+            let (first, last) = (run[0], run[run.len() - 1]);
+            if (points[last].end_time - points[first].start_time).num_seconds() > MAX_OUTLIER_RUN_SECS {
+                continue;
+            }
+            let before = position.checked_sub(1).map(|before| runs[before]);
+            let after = runs.get(position + 1);
+            // The point on the track the run jumped away from
+            let anchor = match (before, after) {
+                (Some(before), Some(after)) => {
+                    let (from, to) = (before[before.len() - 1], after[0]);
+                    reachable(&points[from], &points[to]).then_some(from)
+                },
+                (None, Some(after)) => {
+                    let returns = runs.get(position + 2).is_some_and(|later| reachable(&points[last], &points[later[0]]));
+                    (after.len() > run.len() && !returns).then_some(after[0])
+                },
+                (Some(before), None) => {
+                    let returns = position.checked_sub(2).is_some_and(|earlier| reachable(&points[runs[earlier][runs[earlier].len() - 1]], &points[first]));
+                    (before.len() > run.len() && !returns).then_some(before[before.len() - 1])
+                },
+                (None, None) => None,
+            };
+            let Some(anchor) = anchor else { continue };
+            for &index in run.iter() {
+                let (from, to) = match anchor < index {
+                    true => (&points[anchor], &points[index]),
+                    false => (&points[index], &points[anchor]),
+                };
+                let point = &points[index];
+                found.push(Outlier {
+                    index,
+                    start_time: point.start_time,
+                    latitude: point.latitude,
+                    longitude: point.longitude,
+                    speed_kmh: implied_speed_kmh(from, to),
+                    max_speed_kmh: limits.between(from, to),
+                });
+            }
+        }
+        if found.is_empty() {
+            break;
+        }
+        for outlier in &found {
+            removed[outlier.index] = true;
+        }
+        kept.retain(|index| !removed[*index]);
+        outliers.extend(found);
+    }
+    outliers.sort_by_key(|outlier| outlier.index);
+    outliers
+}
+
+/// A copy of the record without the outliers, and the outliers that were removed
+pub fn without_outliers(record: &SpaceTimeRecord, limits: &SpeedLimits) -> (SpaceTimeRecord, Vec<Outlier>) {
+    let outliers = find_outliers(record, limits);
+    let mut outlier_indices = outliers.iter().map(|outlier| outlier.index).peekable();
+    let points = record.points.iter().enumerate()
+        .filter(|(index, _)| match outlier_indices.peek() == Some(index) {
+            true => {
+                outlier_indices.next();
+                false
+            },
+            false => true,
+        })
+        .map(|(_, point)| point.clone())
+        .collect();
+    (SpaceTimeRecord::new(points, record.warnings.clone()), outliers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_point;
+
+    fn point(start: i64, latitude: f64, longitude: f64, activity: Option<Activity>) -> SpaceTimePoint {
+        SpaceTimePoint { activity, ..test_point(start, start + 60, latitude, longitude) }
+    }
+
+    #[test]
+    fn test_implied_speed() {
+        // About 1.1km in a minute
+        let speed = implied_speed_kmh(&point(0, 40.0, -75.0, None), &point(60, 40.01, -75.0, None));
+        assert!((speed - 66.7).abs() < 0.5, "{}", speed);
+        // Jitter doesn't count
+        assert_eq!(implied_speed_kmh(&point(0, 40.0, -75.0, None), &point(1, 40.0005, -75.0, None)), 0.0);
+        let mut inaccurate = point(60, 40.01, -75.0, None);
+        inaccurate.accuracy = Some(1200.0);
+        assert_eq!(implied_speed_kmh(&point(0, 40.0, -75.0, None), &inaccurate), 0.0);
+    }
+
+    #[test]
+    fn test_without_outliers() {
+        let walking = Some(Activity::Walking);
+        let record = SpaceTimeRecord::new(vec![
+            point(0, 40.0, -75.0, walking),
+            point(60, 40.0005, -75.0, walking),
+            // A cell tower fix 300km away for two samples
+            point(120, 42.7, -75.0, None),
+            point(180, 42.7, -75.0, None),
+            point(240, 40.001, -75.0, walking),
+            point(300, 40.0015, -75.0, walking),
+        ], Vec::new());
+        let (cleaned, outliers) = without_outliers(&record, &SpeedLimits::default());
+        assert_eq!(outliers.iter().map(|outlier| outlier.index).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(outliers[0].speed_kmh > outliers[0].max_speed_kmh);
+        assert_eq!(cleaned.points.len(), 4);
+        assert!(cleaned.points.iter().all(|point| point.latitude < 41.0));
+    }
+
+    #[test]
+    fn test_journey_is_not_an_outlier() {
+        // A flight without points along the way, the track stays at the destination
+        let record = SpaceTimeRecord::new(vec![
+            point(0, 51.47, -0.45, Some(Activity::Stationary)),
+            point(3600, 51.47, -0.45, Some(Activity::Stationary)),
+            point(7200, 51.47, -0.45, Some(Activity::Stationary)),
+            point(14 * 3600, 40.64, -73.78, None),
+            point(14 * 3600 + 60, 40.641, -73.78, Some(Activity::Walking)),
+            point(14 * 3600 + 120, 40.642, -73.78, Some(Activity::Walking)),
+        ], Vec::new());
+        assert!(find_outliers(&record, &SpeedLimits::default()).is_empty());
+
+        // Too fast for walking when the track comes back
+        let limits = SpeedLimits { unknown: 10.0, ..SpeedLimits::default() };
+        let record = SpaceTimeRecord::new(vec![point(0, 40.0, -75.0, None), point(3600, 40.2, -75.0, None), point(7200, 40.0, -75.0, None)], Vec::new());
+        assert_eq!(find_outliers(&record, &limits).len(), 1);
+        assert!(find_outliers(&record, &SpeedLimits::default()).is_empty());
+    }
+
+    #[test]
+    fn test_outliers_at_the_edges() {
+        let walking = Some(Activity::Walking);
+        let track = |start: i64| (0..5).map(move |i| point(start + i * 60, 40.0 + i as f64 * 0.001, -75.0, walking));
+
+        // A bad first point, with nothing before it to come back to
+        let mut points = vec![point(0, 42.7, -75.0, None)];
+        points.extend(track(60));
+        let outliers = find_outliers(&SpaceTimeRecord::new(points, Vec::new()), &SpeedLimits::default());
+        assert_eq!(outliers.iter().map(|outlier| outlier.index).collect::<Vec<_>>(), vec![0]);
+        assert!(outliers[0].speed_kmh > outliers[0].max_speed_kmh);
+
+        // Bad points at the end, with nothing after them
+        let mut points: Vec<_> = track(0).collect();
+        points.extend([point(300, 42.7, -75.0, None), point(360, 42.7, -75.0, None)]);
+        let outliers = find_outliers(&SpaceTimeRecord::new(points, Vec::new()), &SpeedLimits::default());
+        assert_eq!(outliers.iter().map(|outlier| outlier.index).collect::<Vec<_>>(), vec![5, 6]);
+    }
+
+    #[test]
+    fn test_long_outlier_run() {
+        // Six samples from a far away cell tower in the middle of a walk
+        let walking = Some(Activity::Walking);
+        let mut points: Vec<_> = (0..3).map(|i| point(i * 60, 40.0, -75.0, walking)).collect();
+        points.extend((3..9).map(|i| point(i * 60, 42.7 + i as f64 * 0.0001, -75.0, None)));
+        points.extend((9..12).map(|i| point(i * 60, 40.0005, -75.0, walking)));
+        let (cleaned, outliers) = without_outliers(&SpaceTimeRecord::new(points, Vec::new()), &SpeedLimits::default());
+        assert_eq!(outliers.iter().map(|outlier| outlier.index).collect::<Vec<_>>(), (3..9).collect::<Vec<_>>());
+        assert_eq!(cleaned.points.len(), 6);
+
+        // The same run lasting hours is a trip
+        let mut points: Vec<_> = (0..3).map(|i| point(i * 60, 40.0, -75.0, walking)).collect();
+        points.extend((3..9).map(|i| point(i * 3600, 42.7, -75.0, None)));
+        points.extend((9..12).map(|i| point(i * 3600, 40.0005, -75.0, walking)));
+        assert!(find_outliers(&SpaceTimeRecord::new(points, Vec::new()), &SpeedLimits::default()).is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{model::{Activity, SpaceTimeRecord, SpaceTimePoint}, timezone::utc_offset_at};
+use rstar::{RTree, RTreeObject, AABB, PointDistance};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+impl RTreeObject for SpaceTimePoint {
+    type Envelope = AABB<[f64; 4]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point([self.latitude, self.longitude, self.start_time.timestamp() as f64, self.end_time.timestamp() as f64])
+    }
+}
+
+impl SpaceTimePoint {
+    const TEMPORAL_WEIGHT: f64 = 0.5;
+    const SPATIAL_WEIGHT: f64 = 1.0 - Self::TEMPORAL_WEIGHT;
+}
+
+impl PointDistance for SpaceTimePoint {
+    fn distance_2(&self, point: &[f64; 4]) -> f64 {
+        let spatial_distance = self.haversine_distance(point[0], point[1]);
+        let temporal_distance = self.temporal_distance(point[2], point[3]);
+
+        (Self::SPATIAL_WEIGHT * spatial_distance).powi(2) + (Self::TEMPORAL_WEIGHT * temporal_distance).powi(2)
+    }
+
+    fn contains_point(&self, point: &[f64; 4]) -> bool {
+        self.latitude == point[0] && self.longitude == point[1] && self.start_time.timestamp() as f64 == point[2] && self.end_time.timestamp() as f64 == point[3]
+    }
+
+    fn distance_2_if_less_or_equal(&self, point: &[f64; 4], max_distance_2: f64) -> Option<f64> {
+        let temporal_component = (self.temporal_distance(point[2], point[3]) * Self::TEMPORAL_WEIGHT).powi(2);
+        if temporal_component > max_distance_2 {
+            return None;
+        }
+
+        // calculate spatial distance lower bound using more efficient euclidean distance calculation
+        let spatial_component = (self.euclidean_distance(point[0], point[1]) * Self::SPATIAL_WEIGHT).powi(2);
+        if spatial_component + temporal_component > max_distance_2 {
+            return None;
+        }
+
+        // return actual squared distance using haversine formula for spatial component
+        Some(temporal_component + (self.haversine_distance(point[0], point[1]) * Self::SPATIAL_WEIGHT).powi(2))
+    }
+}
+
+/// Thresholds for when two people count as having been near each other
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EncounterOptions {
+    /// Furthest apart two points can be, in kilometers
+    pub max_distance_km: f64,
+    /// Longest time between two points that still counts as being there at the same time
+    pub max_time_gap_secs: i64,
+    /// Matches closer together in time than this are merged into a single encounter
+    pub episode_gap_secs: i64,
+}
+
+impl Default for EncounterOptions {
+    fn default() -> Self {
+        EncounterOptions {
+            max_distance_km: 0.1,
+            max_time_gap_secs: 5 * 60,
+            episode_gap_secs: 15 * 60,
+        }
+    }
+}
+
+/// A period of time during which both people were within the encounter thresholds of each other
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Encounter {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub min_distance_km: f64,
+    /// Midpoint of the closest pair of points
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Number of point pairs that matched during the encounter
+    pub matches: usize,
+    /// What each person was doing at the closest approach, if their history says
+    pub activity1: Option<Activity>,
+    pub activity2: Option<Activity>,
+    /// Offset of the local time there from UTC, when either person's history gives one
+    pub utc_offset_secs: Option<i32>,
+    /// How notable the encounter is from 0 to 100, higher when closer and longer
+    pub score: f64,
+}
+
+impl Encounter {
+    /// Minutes over which the duration part of the score approaches its maximum
+    const SCORE_DURATION_MINUTES: f64 = 30.0;
+
+    pub fn duration(&self) -> Duration {
+        self.end_time - self.start_time
+    }
+
+    /// The offset of local time from UTC where the encounter happened, from the histories or else from where it is
+    pub fn utc_offset(&self) -> FixedOffset {
+        match self.utc_offset_secs.and_then(FixedOffset::east_opt) {
+            Some(offset) => offset,
+            None => utc_offset_at(self.latitude, self.longitude, self.start_time),
+        }
+    }
+
+    /// Half the score is for how close the people got relative to the threshold,
+    /// the other half for how long they stayed together
+    fn compute_score(&self, options: &EncounterOptions) -> f64 {
+        let closeness = (1.0 - self.min_distance_km / options.max_distance_km).clamp(0.0, 1.0);
+        let persistence = 1.0 - (-(self.duration().num_seconds() as f64 / 60.0) / Self::SCORE_DURATION_MINUTES).exp();
+        50.0 * closeness + 50.0 * persistence
+    }
+}
+
+/// Spatio-temporal index over the points of one SpaceTimeRecord
+pub struct SpaceTimeIndex {
+    tree: RTree<SpaceTimePoint>,
+}
+
+impl SpaceTimeIndex {
+    const KM_PER_DEGREE_LATITUDE: f64 = 110.574;
+    const KM_PER_DEGREE_LONGITUDE: f64 = 111.320; // at the equator, shrinks with cos(latitude)
+
+    pub fn new(record: &SpaceTimeRecord) -> Self {
+        SpaceTimeIndex { tree: RTree::bulk_load(record.points.clone()) }
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// Points that could be within the thresholds of `point`, found with a bounding box query
+    /// Candidates still need an exact distance check
+    fn candidates<'a>(&'a self, point: &SpaceTimePoint, options: &EncounterOptions) -> impl Iterator<Item = &'a SpaceTimePoint> {
+        let delta_lat = options.max_distance_km / Self::KM_PER_DEGREE_LATITUDE;
+        let delta_lon = options.max_distance_km / (Self::KM_PER_DEGREE_LONGITUDE * point.latitude.to_radians().cos().max(0.01));
+        let gap = options.max_time_gap_secs as f64;
+        // A candidate has to start before this point ends and end after it starts, give or take the allowed gap
+        let envelope = AABB::from_corners(
+            [point.latitude - delta_lat, point.longitude - delta_lon, f64::MIN, point.start_time.timestamp() as f64 - gap],
+            [point.latitude + delta_lat, point.longitude + delta_lon, point.end_time.timestamp() as f64 + gap, f64::MAX],
+        );
+        self.tree.locate_in_envelope_intersecting(&envelope)
+    }
+}
+
+/// Find every encounter between the points of `record` and the indexed record
+pub fn find_encounters(record: &SpaceTimeRecord, index: &SpaceTimeIndex, options: &EncounterOptions) -> Vec<Encounter> {
+    find_encounters_with_progress(record, index, options, |_, _| ())
+}
+
+/// Like `find_encounters`, calling `progress` with how many of the record's points have been matched
+/// out of the total, roughly every percent and once at the end
+pub fn find_encounters_with_progress(record: &SpaceTimeRecord, index: &SpaceTimeIndex, options: &EncounterOptions, mut progress: impl FnMut(usize, usize)) -> Vec<Encounter> {
+    let total = record.points.len();
+    let progress_interval = (total / 100).max(1);
+    let mut matches = Vec::new();
+    for (i, point) in record.points.iter().enumerate() {
+        if i % progress_interval == 0 {
+            progress(i, total);
+        }
+        for candidate in index.candidates(point, options) {
+            let distance = point.haversine_distance(candidate.latitude, candidate.longitude);
+            if distance > options.max_distance_km {
+                continue;
+            }
+            // The time both points cover, or the gap between them if they don't overlap
+            let start_time = point.start_time.max(candidate.start_time);
+            let end_time = point.end_time.min(candidate.end_time);
+            matches.push(Encounter {
+                start_time: start_time.min(end_time),
+                end_time: start_time.max(end_time),
+                min_distance_km: distance,
+                latitude: (point.latitude + candidate.latitude) / 2.0,
+                longitude: (point.longitude + candidate.longitude) / 2.0,
+                matches: 1,
+                activity1: point.activity,
+                activity2: candidate.activity,
+                utc_offset_secs: point.utc_offset_secs.or(candidate.utc_offset_secs),
+                score: 0.0,
+            });
+        }
+    }
+    progress(total, total);
+    merge_encounters(matches, options)
+}
+
+/// Merge encounters that overlap or are within the episode gap of each other into single episodes
+pub fn merge_encounters(mut encounters: Vec<Encounter>, options: &EncounterOptions) -> Vec<Encounter> {
+    encounters.sort_by_key(|encounter| encounter.start_time);
+    let episode_gap = Duration::seconds(options.episode_gap_secs);
+
+    let mut merged: Vec<Encounter> = Vec::new();
+    for encounter in encounters {
+        match merged.last_mut() {
+            Some(episode) if encounter.start_time <= episode.end_time + episode_gap => {
+                episode.end_time = episode.end_time.max(encounter.end_time);
+                episode.matches += encounter.matches;
+                if encounter.min_distance_km < episode.min_distance_km {
+                    episode.min_distance_km = encounter.min_distance_km;
+                    episode.latitude = encounter.latitude;
+                    episode.longitude = encounter.longitude;
+                    episode.activity1 = encounter.activity1;
+                    episode.activity2 = encounter.activity2;
+                    episode.utc_offset_secs = encounter.utc_offset_secs;
+                }
+            },
+            _ => merged.push(encounter),
+        }
+    }
+    for episode in &mut merged {
+        episode.score = episode.compute_score(options);
+    }
+    merged
+}
+
+/// Split matching a record into up to `shard_count` independent pieces by time range, for running in parallel.
+/// Each shard is a contiguous run of the record's points, and the index only gives points near them in time,
+/// so merging what every shard finds against the whole other record with `merge_encounters` gives the same
+/// result as `analyze`
+pub fn time_shards(record: &SpaceTimeRecord, shard_count: usize) -> Vec<Range<usize>> {
+    let shard_size = record.points.len().div_ceil(shard_count.max(1)).max(1);
+    (0..record.points.len()).step_by(shard_size).map(|start| start..(start + shard_size).min(record.points.len())).collect()
+}
+
+/// Index the second record and find its encounters with the first
+pub fn analyze(record1: &SpaceTimeRecord, record2: &SpaceTimeRecord, options: &EncounterOptions) -> Vec<Encounter> {
+    let index = SpaceTimeIndex::new(record2);
+    find_encounters(record1, &index, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_point;
+
+    fn with_activity(point: SpaceTimePoint, activity: Activity) -> SpaceTimePoint {
+        SpaceTimePoint { activity: Some(activity), ..point }
+    }
+
+    #[test]
+    fn test_find_encounters() {
+        let record1 = SpaceTimeRecord::new(vec![
+            test_point(0, 600, 40.0, -75.0),
+            test_point(600, 1200, 40.0005, -75.0), // ~55m from the first point, still together
+            test_point(5000, 6000, 41.0, -75.0), // far away
+            test_point(9000, 9600, 40.0, -75.0), // same place but much later
+        ], Vec::new());
+        let record2 = SpaceTimeRecord::new(vec![
+            with_activity(test_point(300, 900, 40.0001, -75.0001), Activity::Walking),
+            test_point(5000, 6000, 40.0, -75.0),
+            test_point(20000, 21000, 40.0, -75.0),
+        ], Vec::new());
+
+        let encounters = analyze(&record1, &record2, &EncounterOptions::default());
+        assert_eq!(encounters.len(), 1);
+        let encounter = &encounters[0];
+        assert_eq!(encounter.start_time.timestamp(), 300);
+        assert_eq!(encounter.end_time.timestamp(), 900);
+        assert_eq!(encounter.matches, 2);
+        assert!(encounter.min_distance_km < 0.02, "Distance was actually {}", encounter.min_distance_km);
+        assert_eq!(encounter.activity1, None);
+        assert_eq!(encounter.activity2, Some(Activity::Walking));
+        assert!(encounter.score > 50.0 && encounter.score < 100.0, "Score was actually {}", encounter.score);
+    }
+
+    #[test]
+    fn test_find_encounters_time_gap() {
+        let record1 = SpaceTimeRecord::new(vec![test_point(0, 100, 40.0, -75.0)], Vec::new());
+        let record2 = SpaceTimeRecord::new(vec![test_point(200, 300, 40.0, -75.0)], Vec::new());
+
+        let options = EncounterOptions { max_time_gap_secs: 60, ..EncounterOptions::default() };
+        assert!(analyze(&record1, &record2, &options).is_empty());
+
+        let options = EncounterOptions { max_time_gap_secs: 120, ..EncounterOptions::default() };
+        let encounters = analyze(&record1, &record2, &options);
+        assert_eq!(encounters.len(), 1);
+        assert_eq!(encounters[0].start_time.timestamp(), 100);
+        assert_eq!(encounters[0].end_time.timestamp(), 200);
+    }
+
+    #[test]
+    fn test_find_encounters_progress() {
+        let record1 = SpaceTimeRecord::new((0..250).map(|i| test_point(i * 10, i * 10 + 10, 40.0, -75.0)).collect(), Vec::new());
+        let record2 = SpaceTimeRecord::new(vec![test_point(0, 100, 40.0, -75.0)], Vec::new());
+        let mut reports = Vec::new();
+        find_encounters_with_progress(&record1, &SpaceTimeIndex::new(&record2), &EncounterOptions::default(), |done, total| reports.push((done, total)));
+
+        assert_eq!(reports.first(), Some(&(0, 250)));
+        assert_eq!(reports.last(), Some(&(250, 250)));
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(reports.len() <= 126);
+    }
+
+    #[test]
+    fn test_time_shards() {
+        // Two people walking the same loop, sometimes together
+        let record1 = SpaceTimeRecord::new((0..100).map(|i| test_point(i * 600, i * 600 + 600, 40.0 + (i % 7) as f64 * 0.0005, -75.0)).collect(), Vec::new());
+        let record2 = SpaceTimeRecord::new((0..150).map(|i| test_point(i * 400, i * 400 + 400, 40.0 + (i % 5) as f64 * 0.0005, -75.0)).collect(), Vec::new());
+        let options = EncounterOptions::default();
+
+        let shards = time_shards(&record1, 3);
+        assert_eq!(shards, vec![0..34, 34..68, 68..100]);
+        assert!(time_shards(&SpaceTimeRecord::new(Vec::new(), Vec::new()), 3).is_empty());
+
+        let index = SpaceTimeIndex::new(&record2);
+        let sharded = merge_encounters(shards.into_iter().flat_map(|shard| {
+            find_encounters(&SpaceTimeRecord::new(record1.points[shard].to_vec(), Vec::new()), &index, &options)
+        }).collect(), &options);
+        assert_eq!(sharded, analyze(&record1, &record2, &options));
+        assert!(!sharded.is_empty());
+    }
+
+    #[test]
+    fn test_merge_encounters() {
+        let encounter = |start: i64, end: i64, distance: f64| Encounter {
+            start_time: DateTime::from_timestamp(start, 0).unwrap(),
+            end_time: DateTime::from_timestamp(end, 0).unwrap(),
+            min_distance_km: distance,
+            latitude: distance,
+            longitude: 0.0,
+            matches: 1,
+            activity1: None,
+            activity2: None,
+            utc_offset_secs: None,
+            score: 0.0,
+        };
+        let options = EncounterOptions { episode_gap_secs: 60, ..EncounterOptions::default() };
+        let merged = merge_encounters(vec![encounter(500, 600, 0.05), encounter(0, 100, 0.08), encounter(130, 200, 0.01)], &options);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].end_time.timestamp(), 200);
+        assert_eq!(merged[0].matches, 2);
+        assert_eq!(merged[0].min_distance_km, 0.01);
+        assert_eq!(merged[0].latitude, 0.01);
+        assert_eq!(merged[1].start_time.timestamp(), 500);
+        assert!(merged[0].score > merged[1].score);
+    }
+}
@@ -0,0 +1,275 @@
+use super::*;
+use chrono::{DateTime, Utc};
+use crate::decoders::errors::DecoderWarning;
+use crate::model::Activity;
+
+/// Describes a record saved in the binary format, readable without decoding the points
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordHeader {
+    pub version: u8,
+    /// Whose history it is, empty when it wasn't given
+    pub person: String,
+    /// Name of the decoder the record was first read with
+    pub source_format: String,
+    pub point_count: usize,
+    /// Start of the first point and end of the last, None when there are no points
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+/// Decoded records saved compactly, for reloading without decoding the original file again and for sharing.
+///
+/// After the header each point is stored as a flags byte followed by varints: its start as milliseconds after
+/// the previous point's start, its duration in milliseconds, and its latitude and longitude in E7 (1e-7 degrees)
+/// as differences from the previous point. Signed values are zigzag encoded so small differences stay small.
+/// Accuracy (in tenths of a meter), activity and UTC offset follow when the flags say so, then the warnings.
+/// Times are kept to the millisecond and coordinates to about a centimeter
+pub struct BinaryRecord;
+
+impl BinaryRecord {
+    const MAGIC: &'static [u8] = b"CERB";
+    /// Bumped whenever the layout changes, older versions are still read
+    pub const VERSION: u8 = 1;
+    /// Extension for files holding a single record
+    pub const EXTENSION: &'static str = "cer";
+
+    const HAS_ACCURACY: u8 = 0x01;
+    const HAS_ACTIVITY: u8 = 0x02;
+    const HAS_UTC_OFFSET: u8 = 0x04;
+
+    const E7: f64 = 1e7;
+    const ACCURACY_SCALE: f64 = 10.0;
+    /// Activity codes are positions in this list, so new activities go at the end
+    const ACTIVITIES: [Activity; 7] = [Activity::Stationary, Activity::Walking, Activity::Running, Activity::Cycling, Activity::Driving, Activity::Transit, Activity::Flying];
+
+    pub fn encode(record: &SpaceTimeRecord, person: &str, source_format: &str) -> Vec<u8> {
+        let mut out = Vec::with_capacity(32 + record.points.len() * 12);
+        out.extend_from_slice(Self::MAGIC);
+        out.push(Self::VERSION);
+        write_string(&mut out, person);
+        write_string(&mut out, source_format);
+        write_varint(&mut out, record.points.len() as u64);
+        if let (Some(first), Some(last_end)) = (record.points.first(), record.points.iter().map(|point| point.end_time).max()) {
+            write_signed(&mut out, first.start_time.timestamp_millis());
+            write_signed(&mut out, last_end.timestamp_millis());
+        }
+
+        let (mut previous_start, mut previous_latitude, mut previous_longitude) = (0, 0, 0);
+        for point in &record.points {
+            let flags = (point.accuracy.is_some() as u8 * Self::HAS_ACCURACY)
+                | (point.activity.is_some() as u8 * Self::HAS_ACTIVITY)
+                | (point.utc_offset_secs.is_some() as u8 * Self::HAS_UTC_OFFSET);
+            out.push(flags);
+
+            let (start, latitude, longitude) = (point.start_time.timestamp_millis(), to_e7(point.latitude), to_e7(point.longitude));
+            write_signed(&mut out, start - previous_start);
+            write_signed(&mut out, point.end_time.timestamp_millis() - start);
+            write_signed(&mut out, latitude - previous_latitude);
+            write_signed(&mut out, longitude - previous_longitude);
+            (previous_start, previous_latitude, previous_longitude) = (start, latitude, longitude);
+
+            if let Some(accuracy) = point.accuracy {
+                write_varint(&mut out, (accuracy.max(0.0) * Self::ACCURACY_SCALE).round() as u64);
+            }
+            if let Some(activity) = point.activity {
+                out.push(Self::ACTIVITIES.iter().position(|known| *known == activity).expect("every activity has a code") as u8);
+            }
+            if let Some(utc_offset_secs) = point.utc_offset_secs {
+                write_signed(&mut out, utc_offset_secs as i64);
+            }
+        }
+
+        write_varint(&mut out, record.warnings.len() as u64);
+        for warning in &record.warnings {
+            write_varint(&mut out, warning.line as u64);
+            write_string(&mut out, &warning.message);
+        }
+        out
+    }
+
+    pub fn read_header(content: &[u8]) -> Result<RecordHeader, DecoderError> {
+        Self::read_header_from(&mut BinaryReader { data: content, position: 0 })
+    }
+
+    fn read_header_from(reader: &mut BinaryReader) -> Result<RecordHeader, DecoderError> {
+        if reader.take(Self::MAGIC.len()).ok() != Some(Self::MAGIC) {
+            return Err(DecoderError::DeserializeError("Missing binary record header".to_string()));
+        }
+        let version = reader.read_u8()?;
+        if version == 0 || version > Self::VERSION {
+            return Err(DecoderError::UnsupportedFormatError(format!("binary record version {}, only up to {} can be read", version, Self::VERSION)));
+        }
+        let person = reader.read_string()?;
+        let source_format = reader.read_string()?;
+        let point_count = reader.read_varint()? as usize;
+        let (start_time, end_time) = match point_count {
+            0 => (None, None),
+            _ => (Some(from_millis(reader.read_signed()?)?), Some(from_millis(reader.read_signed()?)?)),
+        };
+        Ok(RecordHeader { version, person, source_format, point_count, start_time, end_time })
+    }
+
+    pub fn decode(content: &[u8]) -> Result<(RecordHeader, SpaceTimeRecord), DecoderError> {
+        Self::decode_filtered(content, &PointFilter::default())
+    }
+
+    /// Decodes only the points the filter keeps, and none at all when the header's time range is outside it
+    pub fn decode_filtered(content: &[u8], filter: &PointFilter) -> Result<(RecordHeader, SpaceTimeRecord), DecoderError> {
+        let mut reader = BinaryReader { data: content, position: 0 };
+        let header = Self::read_header_from(&mut reader)?;
+        if let (Some(start_time), Some(end_time)) = (header.start_time, header.end_time) {
+            if !filter.overlaps(start_time, end_time) {
+                return Ok((header, SpaceTimeRecord::new(Vec::new(), Vec::new())));
+            }
+        }
+
+        // Each point takes at least 5 bytes, which keeps a corrupt count from reserving too much
+        let mut points = Vec::with_capacity(header.point_count.min(reader.remaining() / 5));
+        let (mut previous_start, mut previous_latitude, mut previous_longitude) = (0i64, 0i64, 0i64);
+        for _ in 0..header.point_count {
+            let flags = reader.read_u8()?;
+            // Wrapping so corrupt values fail as out of range times instead of overflowing
+            let start = previous_start.wrapping_add(reader.read_signed()?);
+            let end = start.wrapping_add(reader.read_signed()?);
+            let latitude = previous_latitude.wrapping_add(reader.read_signed()?);
+            let longitude = previous_longitude.wrapping_add(reader.read_signed()?);
+            (previous_start, previous_latitude, previous_longitude) = (start, latitude, longitude);
+
+            let accuracy = match flags & Self::HAS_ACCURACY {
+                0 => None,
+                _ => Some(reader.read_varint()? as f64 / Self::ACCURACY_SCALE),
+            };
+            let activity = match flags & Self::HAS_ACTIVITY {
+                0 => None,
+                _ => {
+                    let code = reader.read_u8()?;
+                    // An unknown activity is left out rather than failing the whole record
+                    Self::ACTIVITIES.get(code as usize).copied()
+                },
+            };
+            let utc_offset_secs = match flags & Self::HAS_UTC_OFFSET {
+                0 => None,
+                _ => Some(i32::try_from(reader.read_signed()?).map_err(|_| DecoderError::TimeParseError("UTC offset out of range".to_string()))?),
+            };
+            let point = SpaceTimePoint {
+                start_time: from_millis(start)?,
+                end_time: from_millis(end)?,
+                latitude: latitude as f64 / Self::E7,
+                longitude: longitude as f64 / Self::E7,
+                accuracy,
+                activity,
+                utc_offset_secs,
+            };
+            if filter.matches(&point) {
+                points.push(point);
+            }
+        }
+
+        let warning_count = reader.read_varint()? as usize;
+        let mut warnings = Vec::with_capacity(warning_count.min(reader.remaining()));
+        for _ in 0..warning_count {
+            let line = reader.read_varint()? as usize;
+            warnings.push(DecoderWarning { line, message: reader.read_string()? });
+        }
+        Ok((header, SpaceTimeRecord::new(points, warnings)))
+    }
+}
+
+/// Records saved with `BinaryRecord::encode`, see there for the layout
+pub struct BinaryDecoder;
+
+impl Decoder for BinaryDecoder {
+    fn name(&self) -> &str {
+        "Binary record"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        match content.starts_with(BinaryRecord::MAGIC) {
+            true => Confidence::High,
+            false => Confidence::None,
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        BinaryRecord::decode_filtered(content, filter).map(|(_, record)| record)
+    }
+}
+
+fn from_millis(millis: i64) -> Result<DateTime<Utc>, DecoderError> {
+    DateTime::from_timestamp_millis(millis).ok_or_else(|| DecoderError::TimeParseError(format!("Binary record time {}ms is out of range", millis)))
+}
+
+fn to_e7(degrees: f64) -> i64 {
+    (degrees * BinaryRecord::E7).round() as i64
+}
+
+/// LEB128, 7 bits at a time with the high bit set on all but the last byte
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Zigzag, so -1 is 1, 1 is 2 and so on
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    write_varint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.position)
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], DecoderError> {
+        match self.data.get(self.position..self.position.saturating_add(size)) {
+            Some(bytes) => {
+                self.position += size;
+                Ok(bytes)
+            },
+            None => Err(DecoderError::DeserializeError(format!("Binary record truncated at byte {}", self.position)))
+        }
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DecoderError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_varint(&mut self) -> Result<u64, DecoderError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecoderError::DeserializeError(format!("Binary record has an overlong number before byte {}", self.position)))
+    }
+
+    fn read_signed(&mut self) -> Result<i64, DecoderError> {
+        let value = self.read_varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn read_string(&mut self) -> Result<String, DecoderError> {
+        let length = self.read_varint()? as usize;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|err| DecoderError::DeserializeError(format!("Binary record has invalid text: {}", err)))
+    }
+}
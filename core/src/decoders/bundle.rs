@@ -0,0 +1,128 @@
+use super::*;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, ChaCha20Poly1305, Key, Nonce};
+use crate::decoders::binary::BinaryRecord;
+
+/// How much work turning a passphrase into a key takes, stored in the bundle so it can be raised later
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Argon2id memory in KiB
+    pub memory_kib: u32,
+    pub iterations: u32,
+}
+
+impl Default for KdfParams {
+    /// OWASP's recommended minimum for Argon2id, quick enough for a browser on a phone
+    fn default() -> Self {
+        KdfParams { memory_kib: 19 * 1024, iterations: 2 }
+    }
+}
+
+/// A person's decoded record encrypted with a passphrase, so it can be sent over chat or email.
+///
+/// The bundle is a header (magic, version, KDF parameters and a random salt) followed by the record in the
+/// binary record format sealed with ChaCha20-Poly1305. The key comes from the passphrase and salt with Argon2id,
+/// and the header is authenticated along with the record so neither can be changed without the passphrase
+pub struct Bundle;
+
+impl Bundle {
+    const MAGIC: &'static [u8] = b"CEBE";
+    pub const VERSION: u8 = 1;
+    /// Extension for bundle files
+    pub const EXTENSION: &'static str = "ceb";
+    pub const SALT_LENGTH: usize = 16;
+    const HEADER_LENGTH: usize = 4 + 1 + 4 + 4 + Self::SALT_LENGTH;
+    /// Refuse to derive keys with more memory than this, a bundle shouldn't be able to exhaust the reader's memory
+    const MAX_MEMORY_KIB: u32 = 256 * 1024;
+    const MAX_ITERATIONS: u32 = 16;
+
+    pub fn is_bundle(content: &[u8]) -> bool {
+        content.starts_with(Self::MAGIC)
+    }
+
+    /// Encrypt a record. The salt must be random and never reused, every bundle then has its own key,
+    /// which is why a fixed nonce is safe
+    pub fn seal(record: &SpaceTimeRecord, person: &str, source_format: &str, passphrase: &str, salt: [u8; Self::SALT_LENGTH], params: KdfParams) -> Result<Vec<u8>, DecoderError> {
+        let mut header = Vec::with_capacity(Self::HEADER_LENGTH);
+        header.extend_from_slice(Self::MAGIC);
+        header.push(Self::VERSION);
+        header.extend_from_slice(&params.memory_kib.to_le_bytes());
+        header.extend_from_slice(&params.iterations.to_le_bytes());
+        header.extend_from_slice(&salt);
+
+        let cipher = Self::cipher(passphrase, &salt, params)?;
+        let plaintext = BinaryRecord::encode(record, person, source_format);
+        let ciphertext = cipher.encrypt(&Nonce::default(), Payload { msg: &plaintext, aad: &header })
+            .map_err(|_| DecoderError::DecryptionError("Unable to encrypt the bundle".to_string()))?;
+        header.extend_from_slice(&ciphertext);
+        Ok(header)
+    }
+
+    /// The binary record inside a bundle, which also says whose it is
+    pub fn open(content: &[u8], passphrase: &str) -> Result<Vec<u8>, DecoderError> {
+        if !Self::is_bundle(content) || content.len() < Self::HEADER_LENGTH {
+            return Err(DecoderError::DeserializeError("Missing bundle header".to_string()));
+        }
+        let version = content[4];
+        if version == 0 || version > Self::VERSION {
+            return Err(DecoderError::UnsupportedFormatError(format!("bundle version {}, only up to {} can be read", version, Self::VERSION)));
+        }
+        let read_u32 = |at: usize| u32::from_le_bytes([content[at], content[at + 1], content[at + 2], content[at + 3]]);
+        let params = KdfParams { memory_kib: read_u32(5), iterations: read_u32(9) };
+        if params.memory_kib > Self::MAX_MEMORY_KIB || params.iterations > Self::MAX_ITERATIONS {
+            return Err(DecoderError::DecryptionError(format!("bundle asks for {} KiB and {} iterations to open, more than allowed", params.memory_kib, params.iterations)));
+        }
+        let (header, ciphertext) = content.split_at(Self::HEADER_LENGTH);
+        let salt = &header[Self::HEADER_LENGTH - Self::SALT_LENGTH..];
+
+        Self::cipher(passphrase, salt, params)?
+            .decrypt(&Nonce::default(), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| DecoderError::DecryptionError("Wrong passphrase, or the bundle has been changed".to_string()))
+    }
+
+    fn cipher(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<ChaCha20Poly1305, DecoderError> {
+        let argon2_params = Params::new(params.memory_kib, params.iterations, 1, Some(32))
+            .map_err(|err| DecoderError::DecryptionError(format!("Invalid key derivation parameters: {}", err)))?;
+        let mut key = Key::default();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|err| DecoderError::DecryptionError(format!("Unable to derive a key: {}", err)))?;
+        Ok(ChaCha20Poly1305::new(&key))
+    }
+}
+
+/// Encrypted bundles made with `Bundle::seal`. Without a passphrase bundles are still recognised, so the error
+/// says a passphrase is needed rather than that the format is unknown
+#[derive(Default)]
+pub struct BundleDecoder {
+    passphrase: Option<String>,
+}
+
+impl BundleDecoder {
+    pub fn new(passphrase: &str) -> Self {
+        BundleDecoder { passphrase: Some(passphrase.to_string()) }
+    }
+}
+
+impl Decoder for BundleDecoder {
+    fn name(&self) -> &str {
+        "Encrypted bundle"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        match Bundle::is_bundle(content) {
+            true => Confidence::High,
+            false => Confidence::None,
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let passphrase = self.passphrase.as_deref()
+            .ok_or_else(|| DecoderError::DecryptionError("This file is an encrypted bundle, a passphrase is needed to open it".to_string()))?;
+        BinaryRecord::decode_filtered(&Bundle::open(content, passphrase)?, filter).map(|(_, record)| record)
+    }
+}
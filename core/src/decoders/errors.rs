@@ -8,6 +8,8 @@ pub enum DecoderError {
     EmptyEntryError(String),
     TimeParseError(String),
     GeoParseError(String),
+    UnsupportedFormatError(String),
+    DecryptionError(String),
 }
 
 impl fmt::Display for DecoderError {
@@ -17,10 +19,25 @@ impl fmt::Display for DecoderError {
             DecoderError::EmptyEntryError(msg) => write!(f, "Empty Entry Error: {}", msg),
             DecoderError::TimeParseError(msg) => write!(f, "UTC Parsing Error: {}", msg),
             DecoderError::GeoParseError(msg) => write!(f, "Geo Parse Error: {}", msg),
+            DecoderError::UnsupportedFormatError(filename) => write!(f, "Unsupported File Format: {}", filename),
+            DecoderError::DecryptionError(msg) => write!(f, "Decryption Error: {}", msg),
         }
     }
 }
 
+/// A problem with part of a file that was skipped without failing the whole decode
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DecoderWarning {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DecoderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
 impl From<serde_json::Error> for DecoderError {
     fn from(err: serde_json::Error) -> Self {
         DecoderError::DeserializeError(format!("Failed to deserialize json {:#?} at line, col {},{}", err.classify(), err.line(),err.column()))
@@ -0,0 +1,291 @@
+use super::*;
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+
+/// Decoded `record` messages of a Garmin FIT activity file
+/// Only the fields needed to build SpaceTimePoints are kept, everything else is skipped
+#[derive(Debug)]
+pub struct FitRecords {
+    records: Vec<FitRecord>,
+}
+
+#[derive(Debug)]
+struct FitRecord {
+    timestamp: u32,
+    position_lat: i32,
+    position_long: i32,
+}
+
+#[derive(Debug)]
+struct FieldDefinition {
+    number: u8,
+    size: u8,
+}
+
+#[derive(Debug)]
+struct MessageDefinition {
+    big_endian: bool,
+    global_message_number: u16,
+    fields: Vec<FieldDefinition>,
+    developer_data_size: usize,
+}
+
+struct FitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl FitRecords {
+    const FILE_TYPE: &'static [u8] = b".FIT";
+    const FIT_EPOCH_OFFSET: i64 = 631065600; // seconds between unix epoch and 1989-12-31T00:00:00Z
+    const SEMICIRCLES_TO_DEGREES: f64 = 180.0 / 2147483648.0; // 180 / 2^31
+
+    const RECORD_MESSAGE: u16 = 20;
+    const TIMESTAMP_FIELD: u8 = 253;
+    const POSITION_LAT_FIELD: u8 = 0;
+    const POSITION_LONG_FIELD: u8 = 1;
+
+    const INVALID_UINT32: u32 = 0xFFFFFFFF;
+    const INVALID_SINT32: i32 = 0x7FFFFFFF;
+
+    const COMPRESSED_HEADER_MASK: u8 = 0x80;
+    const DEFINITION_MESSAGE_MASK: u8 = 0x40;
+    const DEVELOPER_DATA_MASK: u8 = 0x20;
+    const LOCAL_MESSAGE_TYPE_MASK: u8 = 0x0F;
+
+    fn parse(content: &[u8]) -> Result<Self, DecoderError> {
+        let header_size = *content.first().ok_or_else(|| DecoderError::DeserializeError("FIT file is empty".to_string()))? as usize;
+        if header_size < 12 || content.len() < header_size || &content[8..12] != Self::FILE_TYPE {
+            return Err(DecoderError::DeserializeError("Missing FIT file header".to_string()));
+        }
+        let data_size = u32::from_le_bytes([content[4], content[5], content[6], content[7]]) as usize;
+        let data_end = header_size + data_size;
+        if content.len() < data_end {
+            return Err(DecoderError::DeserializeError(format!("FIT file is truncated, expected {} bytes of data but found {}", data_size, content.len() - header_size)));
+        }
+
+        let mut reader = FitReader { data: &content[header_size..data_end], position: 0 };
+        let mut definitions: HashMap<u8, MessageDefinition> = HashMap::new();
+        let mut records = Vec::new();
+        let mut last_timestamp: Option<u32> = None;
+
+        while !reader.is_empty() {
+            let header = reader.read_u8()?;
+
+            if header & Self::COMPRESSED_HEADER_MASK != 0 {
+                // Compressed timestamp header: 2 bits of local message type and a 5 bit offset from the last timestamp
+                let local_message_type = (header >> 5) & 0x03;
+                let time_offset = (header & 0x1F) as u32;
+                let timestamp = match last_timestamp {
+                    Some(last) => {
+                        let rollover = if time_offset >= (last & 0x1F) { 0 } else { 0x20 };
+                        (last & !0x1F) + time_offset + rollover
+                    },
+                    None => return Err(DecoderError::DeserializeError("FIT compressed timestamp found before any full timestamp".to_string()))
+                };
+                last_timestamp = Some(timestamp);
+                let definition = Self::get_definition(&definitions, local_message_type)?;
+                if let Some(record) = Self::read_data_message(&mut reader, definition, Some(timestamp))? {
+                    records.push(record);
+                }
+            } else if header & Self::DEFINITION_MESSAGE_MASK != 0 {
+                let definition = Self::read_definition_message(&mut reader, header & Self::DEVELOPER_DATA_MASK != 0)?;
+                definitions.insert(header & Self::LOCAL_MESSAGE_TYPE_MASK, definition);
+            } else {
+                let definition = Self::get_definition(&definitions, header & Self::LOCAL_MESSAGE_TYPE_MASK)?;
+                let timestamp = Self::peek_timestamp(&reader, definition)?;
+                if timestamp.is_some() {
+                    last_timestamp = timestamp;
+                }
+                if let Some(record) = Self::read_data_message(&mut reader, definition, timestamp)? {
+                    records.push(record);
+                }
+            }
+        }
+
+        Ok(FitRecords { records })
+    }
+
+    fn get_definition(definitions: &HashMap<u8, MessageDefinition>, local_message_type: u8) -> Result<&MessageDefinition, DecoderError> {
+        definitions.get(&local_message_type).ok_or_else(|| DecoderError::DeserializeError(format!("FIT data message uses undefined local message type {}", local_message_type)))
+    }
+
+    fn read_definition_message(reader: &mut FitReader, has_developer_data: bool) -> Result<MessageDefinition, DecoderError> {
+        reader.skip(1)?; // reserved
+        let big_endian = reader.read_u8()? == 1;
+        let global_message_number = reader.read_u16(big_endian)?;
+        let num_fields = reader.read_u8()?;
+
+        let mut fields = Vec::with_capacity(num_fields as usize);
+        for _ in 0..num_fields {
+            let number = reader.read_u8()?;
+            let size = reader.read_u8()?;
+            reader.skip(1)?; // base type, all fields we care about have fixed types
+            fields.push(FieldDefinition { number, size });
+        }
+
+        let mut developer_data_size = 0;
+        if has_developer_data {
+            let num_developer_fields = reader.read_u8()?;
+            for _ in 0..num_developer_fields {
+                reader.skip(1)?; // field number
+                developer_data_size += reader.read_u8()? as usize;
+                reader.skip(1)?; // developer data index
+            }
+        }
+
+        Ok(MessageDefinition { big_endian, global_message_number, fields, developer_data_size })
+    }
+
+    /// Find the timestamp field of a data message without consuming it
+    fn peek_timestamp(reader: &FitReader, definition: &MessageDefinition) -> Result<Option<u32>, DecoderError> {
+        let mut offset = 0;
+        for field in &definition.fields {
+            if field.number == Self::TIMESTAMP_FIELD && field.size == 4 {
+                let timestamp = reader.peek_u32(offset, definition.big_endian)?;
+                return Ok((timestamp != Self::INVALID_UINT32).then_some(timestamp));
+            }
+            offset += field.size as usize;
+        }
+        Ok(None)
+    }
+
+    fn read_data_message(reader: &mut FitReader, definition: &MessageDefinition, timestamp: Option<u32>) -> Result<Option<FitRecord>, DecoderError> {
+        if definition.global_message_number != Self::RECORD_MESSAGE {
+            let message_size: usize = definition.fields.iter().map(|field| field.size as usize).sum();
+            reader.skip(message_size + definition.developer_data_size)?;
+            return Ok(None);
+        }
+
+        let mut position_lat = Self::INVALID_SINT32;
+        let mut position_long = Self::INVALID_SINT32;
+        for field in &definition.fields {
+            match (field.number, field.size) {
+                (Self::POSITION_LAT_FIELD, 4) => position_lat = reader.read_u32(definition.big_endian)? as i32,
+                (Self::POSITION_LONG_FIELD, 4) => position_long = reader.read_u32(definition.big_endian)? as i32,
+                _ => reader.skip(field.size as usize)?,
+            }
+        }
+        reader.skip(definition.developer_data_size)?;
+
+        match timestamp {
+            Some(timestamp) if position_lat != Self::INVALID_SINT32 && position_long != Self::INVALID_SINT32 => {
+                Ok(Some(FitRecord { timestamp, position_lat, position_long }))
+            },
+            _ => Ok(None) // records without a fix (e.g. indoor or before GPS lock) carry no location
+        }
+    }
+}
+
+impl FitRecord {
+    fn get_timestamp(&self) -> Result<DateTime<Utc>, DecoderError> {
+        DateTime::from_timestamp(FitRecords::FIT_EPOCH_OFFSET + self.timestamp as i64, 0)
+            .ok_or_else(|| DecoderError::TimeParseError(format!("Error converting FIT timestamp {} to DateTime", self.timestamp)))
+    }
+}
+
+impl<'a> FitReader<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], DecoderError> {
+        match self.data.get(self.position..self.position + size) {
+            Some(bytes) => {
+                self.position += size;
+                Ok(bytes)
+            },
+            None => Err(DecoderError::DeserializeError(format!("FIT message truncated at byte {}", self.position)))
+        }
+    }
+
+    fn skip(&mut self, size: usize) -> Result<(), DecoderError> {
+        self.take(size).map(|_| ())
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DecoderError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u16(&mut self, big_endian: bool) -> Result<u16, DecoderError> {
+        let bytes = self.take(2)?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn read_u32(&mut self, big_endian: bool) -> Result<u32, DecoderError> {
+        let value = self.peek_u32(0, big_endian)?;
+        self.skip(4)?;
+        Ok(value)
+    }
+
+    fn peek_u32(&self, offset: usize, big_endian: bool) -> Result<u32, DecoderError> {
+        let start = self.position + offset;
+        match self.data.get(start..start + 4) {
+            Some(bytes) => {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                Ok(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+            },
+            None => Err(DecoderError::DeserializeError(format!("FIT message truncated at byte {}", start)))
+        }
+    }
+}
+
+/// Garmin FIT activities, as written by Garmin, Wahoo and most other sports devices
+pub struct FitDecoder;
+
+impl Decoder for FitDecoder {
+    fn name(&self) -> &str {
+        "FIT"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        match content.get(8..12) {
+            Some(file_type) if file_type == FitRecords::FILE_TYPE => Confidence::High,
+            _ => Confidence::None
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let points = FitRecords::try_from(content)?.into_space_time_points(filter)?;
+        Ok(SpaceTimeRecord::new(points, Vec::new()))
+    }
+}
+
+impl FitRecords {
+    fn into_space_time_points(self, filter: &PointFilter) -> PointsResult {
+        let mut space_time_points = Vec::with_capacity(self.records.len());
+        for (i, record) in self.records.iter().enumerate() {
+            let start_time = record.get_timestamp()?;
+            let end_time = match self.records.get(i + 1) {
+                Some(next_record) => next_record.get_timestamp()?,
+                None => start_time // Use the same time for the last point
+            };
+
+            let point = SpaceTimePoint {
+                start_time,
+                end_time,
+                latitude: record.position_lat as f64 * FitRecords::SEMICIRCLES_TO_DEGREES,
+                longitude: record.position_long as f64 * FitRecords::SEMICIRCLES_TO_DEGREES,
+                accuracy: None,
+                activity: None,
+                utc_offset_secs: None,
+            };
+            if filter.matches(&point) {
+                space_time_points.push(point);
+            }
+        }
+        Ok(space_time_points)
+    }
+}
+
+impl TryFrom<&[u8]> for FitRecords {
+    type Error = DecoderError;
+    fn try_from(content: &[u8]) -> Result<Self, Self::Error> {
+        FitRecords::parse(content)
+    }
+}
@@ -8,10 +8,6 @@ pub struct GpxRecords {
     trk: Vec<Track>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Gpx {
-}
-
 #[derive(Debug, Deserialize)]
 struct Track {
     trkseg: Vec<TrackSegment>,
@@ -31,18 +27,43 @@ struct TrackPoint {
     time: String,
 }
 
-impl Into<PointsResult> for GpxRecords {
-    fn into(self) -> PointsResult {
+/// GPS Exchange Format tracks
+pub struct GpxDecoder;
+
+impl Decoder for GpxDecoder {
+    fn name(&self) -> &str {
+        "GPX"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        match sniff_head(content).contains("<gpx") {
+            true => Confidence::High,
+            false => Confidence::None
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let points = GpxRecords::from_str(as_text(content)?)?.into_space_time_points(filter)?;
+        Ok(SpaceTimeRecord::new(points, Vec::new()))
+    }
+}
+
+impl GpxRecords {
+    fn into_space_time_points(self, filter: &PointFilter) -> PointsResult {
         let mut space_time_points = Vec::with_capacity(self.trk.len());
         for track in &self.trk {
-            space_time_points.extend(track.to_space_time_points()?);
+            space_time_points.extend(track.to_space_time_points(filter)?);
         }
         Ok(space_time_points)
     }
 }
 
 impl Track {
-    pub fn to_space_time_points(&self) -> Result<Vec<SpaceTimePoint>, DecoderError> {
+    pub fn to_space_time_points(&self, filter: &PointFilter) -> Result<Vec<SpaceTimePoint>, DecoderError> {
         let mut points = Vec::new();
 
         for segment in &self.trkseg {
@@ -54,12 +75,18 @@ impl Track {
                     start_time // Use the same time for the last point
                 };
 
-                points.push(SpaceTimePoint {
+                let point = SpaceTimePoint {
                     start_time,
                     end_time,
                     latitude: point.lat,
                     longitude: point.lon,
-                });
+                    accuracy: None,
+                    activity: None,
+                    utc_offset_secs: source_utc_offset(&point.time),
+                };
+                if filter.matches(&point) {
+                    points.push(point);
+                }
             }
         }
 
@@ -1,53 +1,172 @@
-use super::{errors::DecoderError, PointsResult, SpaceTimePoint};
-use std::str::FromStr;
-use serde::Deserialize;
+use crate::{filter::PointFilter, model::Activity};
+use super::{errors::DecoderError, as_text, sniff_head, source_utc_offset, Confidence, Decoder, PointsResult, RecordResult, SpaceTimePoint, SpaceTimeRecord};
+use std::{fmt, marker::PhantomData};
+use serde::{de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor}, Deserialize, Deserializer};
 use serde_json;
 use chrono::{Duration, DateTime, Utc};
 use shrinkwraprs::Shrinkwrap;
 
-#[derive(Deserialize)]
-#[serde(untagged)]
-pub enum JsonRecord
-{
-    JsonEntries(Vec<JsonEntry>),
-    TimelineObjects(TimeLineObjects),
-    LocationEntries(LocationEntries),
+/// Google Location History / Timeline exports
+pub struct JsonDecoder;
+
+impl Decoder for JsonDecoder {
+    fn name(&self) -> &str {
+        "Google Location History"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        let head = sniff_head(content);
+        if !head.trim_start().starts_with(['{', '[']) {
+            return Confidence::None;
+        }
+        let google_keys = ["\"timelineObjects\"", "\"latitudeE7\"", "\"timelinePath\"", "\"placeLocation\"", "\"startTime\""];
+        match google_keys.iter().any(|key| head.contains(key)) {
+            true => Confidence::High,
+            false => Confidence::Low
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let mut reader = EntryReader { filter, points: Vec::new(), error: None };
+        let mut deserializer = serde_json::Deserializer::from_str(as_text(content)?);
+        let read = ExportSeed(&mut reader).deserialize(&mut deserializer).and_then(|_| deserializer.end());
+        if let Some(err) = reader.error {
+            return Err(err);
+        }
+        read?;
+        Ok(SpaceTimeRecord::new(reader.points, Vec::new()))
+    }
 }
 
 trait IntoSpaceTimePoints {
     fn to_space_time_points(&self) -> PointsResult;
 }
 
-impl Into<PointsResult> for JsonRecord {
-    fn into(self) -> PointsResult {
-        let mut space_time_points: Vec<SpaceTimePoint> = Vec::new();
-        match self {
-            JsonRecord::JsonEntries(entries) => {
-                space_time_points.reserve(entries.len()); // at minimum, there are as many points as entries
-                for entry in entries {
-                    space_time_points.append(&mut entry.to_space_time_points()?);
-                }
-            },
-            JsonRecord::TimelineObjects(timeline_objects) => {
-                space_time_points.reserve(timeline_objects.timeline_objects.len()); // at minimum, there are as many points as entries
-                for entry in &timeline_objects.timeline_objects {
-                    space_time_points.append(&mut entry.to_space_time_points()?);
-                }
+/// An entry that can be turned into points on its own, skipping the work for anything the filter rules out
+trait FilteredEntry {
+    fn to_filtered_space_time_points(&self, filter: &PointFilter) -> PointsResult;
+}
+
+/// Reads an export one entry at a time, turning each into points before the next is read. Exports can hold millions
+/// of entries, this way neither the whole export nor the entries outside the filter are ever held in memory
+struct EntryReader<'f> {
+    filter: &'f PointFilter,
+    points: Vec<SpaceTimePoint>,
+    /// Why an entry's points couldn't be made, kept as serde's errors only carry a message
+    error: Option<DecoderError>,
+}
+
+impl EntryReader<'_> {
+    fn read<E: de::Error>(&mut self, entry: &impl FilteredEntry) -> Result<(), E> {
+        match entry.to_filtered_space_time_points(self.filter) {
+            // Entries that overlap the time range can still have some points outside it or the area
+            Ok(points) => {
+                self.points.extend(points.into_iter().filter(|point| self.filter.matches(point)));
+                Ok(())
             },
-            JsonRecord::LocationEntries(location_entries) => {
-                space_time_points.reserve_exact(location_entries.locations.len()); // We know there are exactly this many entries
-                space_time_points.append(&mut location_entries.to_space_time_points()?);
+            Err(err) => {
+                let message = err.to_string();
+                self.error = Some(err);
+                Err(E::custom(message))
             },
-            _ => return Err(DecoderError::DeserializeError("Unsupported Type".to_string()))
         }
-        Ok(space_time_points)
     }
 }
 
-impl FromStr for JsonRecord {
-    type Err = DecoderError;
-    fn  from_str(content: &str) -> Result<Self, Self::Err> {
-        Ok(serde_json::from_str(content)?)
+/// The whole file, either an array of entries or an object holding `timelineObjects` or `locations`
+struct ExportSeed<'r, 'f>(&'r mut EntryReader<'f>);
+
+impl<'de> DeserializeSeed<'de> for ExportSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ExportSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a Google location history export")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<(), A::Error> {
+        EntriesSeed::<JsonEntry>(self.0, PhantomData).visit_seq(seq)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut found = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "timelineObjects" => map.next_value_seed(EntriesSeed::<TimelineObject>(self.0, PhantomData))?,
+                "locations" => {
+                    // Records.json lists the newest locations first
+                    let first = self.0.points.len();
+                    map.next_value_seed(EntriesSeed::<LocationEntry>(self.0, PhantomData))?;
+                    self.0.points[first..].reverse();
+                },
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                    continue;
+                },
+            }
+            found = true;
+        }
+        match found {
+            true => Ok(()),
+            false => Err(de::Error::custom("no timelineObjects or locations in the export")),
+        }
+    }
+}
+
+/// An array of entries, each handed to the reader as soon as it's deserialized
+struct EntriesSeed<'r, 'f, T>(&'r mut EntryReader<'f>, PhantomData<T>);
+
+impl<'de, T: Deserialize<'de> + FilteredEntry> DeserializeSeed<'de> for EntriesSeed<'_, '_, T> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T: Deserialize<'de> + FilteredEntry> Visitor<'de> for EntriesSeed<'_, '_, T> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of location history entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(entry) = seq.next_element::<T>()? {
+            self.0.read(&entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl FilteredEntry for JsonEntry {
+    fn to_filtered_space_time_points(&self, filter: &PointFilter) -> PointsResult {
+        match filter.overlaps(parse_timestamp_str(&self.start_time)?, parse_timestamp_str(&self.end_time)?) {
+            true => self.to_space_time_points(),
+            false => Ok(Vec::new()),
+        }
+    }
+}
+
+impl FilteredEntry for TimelineObject {
+    fn to_filtered_space_time_points(&self, filter: &PointFilter) -> PointsResult {
+        if let Some(duration) = self.duration() {
+            if !filter.overlaps(parse_timestamp_str(&duration.start_timestamp)?, parse_timestamp_str(&duration.end_timestamp)?) {
+                return Ok(Vec::new());
+            }
+        }
+        self.to_space_time_points()
     }
 }
 
@@ -56,13 +175,6 @@ struct TimestampRfc3339(String);
 #[derive(Shrinkwrap, Deserialize, Debug)]
 struct GeoLocationE7(i64);
 
-#[derive(Deserialize)]
-pub struct TimeLineObjects
-{
-    #[serde(rename = "timelineObjects")]
-    timeline_objects: Vec<TimelineObject>
-}
-
 #[derive(Deserialize)]
 struct TimelineObject
 {
@@ -87,7 +199,8 @@ struct ActivitySegment
     end_location: Location,
     duration: JsonDuration,
     simplified_raw_path: Option<SimplifiedRawPath>,
-    waypoint_path: Option<WaypointPath>
+    waypoint_path: Option<WaypointPath>,
+    activity_type: Option<String>
 }
 
 #[derive(Deserialize)]
@@ -154,6 +267,11 @@ impl IntoSpaceTimePoints for TimelineObject
 
 impl TimelineObject
 {
+    fn duration(&self) -> Option<&JsonDuration> {
+        self.place_visit.as_ref().map(|place_visit| &place_visit.duration)
+            .or(self.activity_segment.as_ref().map(|activity_segment| &activity_segment.duration))
+    }
+
     fn parse_place_visit(&self) -> PointsResult {
         let place_visit = match self.place_visit.as_ref() {
             Some(place_visit) => place_visit,
@@ -165,7 +283,10 @@ impl TimelineObject
                 latitude: parse_geolocation_e7(latitude)?,
                 longitude: parse_geolocation_e7(longitude)?,
                 start_time: parse_timestamp_str(&place_visit.duration.start_timestamp)?,
-                end_time: parse_timestamp_str(&place_visit.duration.end_timestamp)?
+                end_time: parse_timestamp_str(&place_visit.duration.end_timestamp)?,
+                accuracy: None,
+                activity: Some(Activity::Stationary),
+                utc_offset_secs: source_utc_offset(&place_visit.duration.start_timestamp),
             }])
         } else {
             Ok(Vec::new())
@@ -182,6 +303,8 @@ impl TimelineObject
         let end_time = parse_timestamp_str(&activity_segment.duration.end_timestamp)?;
 
         let point_type = self.get_point_type()?;
+        let activity = activity_segment.activity_type.as_deref().and_then(Activity::from_description);
+        let utc_offset_secs = source_utc_offset(&activity_segment.duration.start_timestamp);
         
         let num_points = 2 + match point_type {
             PointType::Waypoints => activity_segment.waypoint_path.as_ref().expect("PointType is classified as Waypoints but is None").waypoints.len(),
@@ -241,7 +364,10 @@ impl TimelineObject
             space_time_points.push(SpaceTimePoint{
                 start_time: last_point_end_time,
                 end_time: point_end_time,
-                latitude, longitude
+                latitude, longitude,
+                accuracy: None,
+                activity,
+                utc_offset_secs,
             });
 
             last_point_end_time = point_end_time;
@@ -275,13 +401,6 @@ impl TimelineObject
     }
 }
 
-#[derive(Deserialize)]
-pub struct LocationEntries
-{
-    #[serde(rename = "locations")]
-    locations: Vec<LocationEntry>
-}
-
 #[derive(Deserialize)]
 struct LocationEntry
 {
@@ -291,27 +410,30 @@ struct LocationEntry
     longitude_e7: GeoLocationE7,
     #[serde(rename = "timestampMs")]
     timestamp_ms: Option<String>,
-    timestamp: Option<TimestampRfc3339>
+    timestamp: Option<TimestampRfc3339>,
+    accuracy: Option<f64>
 }
 
-impl IntoSpaceTimePoints for LocationEntries
-{
-    fn to_space_time_points(&self) -> PointsResult {
-        let mut space_time_points = Vec::new();
-        space_time_points.reserve_exact(self.locations.len());
-
-        for location in self.locations.iter().rev() {
-            let timestamp = location.get_timestamp()?;
-    
-            space_time_points.push(SpaceTimePoint {
-                latitude: parse_geolocation_e7(&location.latitude_e7)?,
-                longitude: parse_geolocation_e7(&location.longitude_e7)?,
-                start_time: timestamp,
-                end_time: timestamp
-            });
+impl FilteredEntry for LocationEntry {
+    /// Locations the filter rules out are never turned into points, these files can hold millions of them
+    fn to_filtered_space_time_points(&self, filter: &PointFilter) -> PointsResult {
+        let timestamp = self.get_timestamp()?;
+        if !filter.overlaps(timestamp, timestamp) {
+            return Ok(Vec::new());
         }
-
-        Ok(space_time_points)
+        let (latitude, longitude) = (parse_geolocation_e7(&self.latitude_e7)?, parse_geolocation_e7(&self.longitude_e7)?);
+        if !filter.contains(latitude, longitude) {
+            return Ok(Vec::new());
+        }
+        Ok(vec![SpaceTimePoint {
+            latitude,
+            longitude,
+            start_time: timestamp,
+            end_time: timestamp,
+            accuracy: self.accuracy,
+            activity: None,
+            utc_offset_secs: self.timestamp.as_ref().and_then(|timestamp| source_utc_offset(timestamp)),
+        }])
     }
 }
 
@@ -327,10 +449,10 @@ impl LocationEntry {
         }
         else if let Some(timestamp) = self.timestamp.as_ref()
         {
-            return Ok(parse_timestamp_str(timestamp)?);
+            return parse_timestamp_str(timestamp);
         }
 
-        return Err(DecoderError::TimeParseError(format!("Location {:?}, {:?} missing timestamp", self.latitude_e7, self.longitude_e7)));
+        Err(DecoderError::TimeParseError(format!("Location {:?}, {:?} missing timestamp", self.latitude_e7, self.longitude_e7)))
     }
 }
 
@@ -345,7 +467,7 @@ pub struct JsonEntry {
     visit: Option<Visit>,
     #[serde(rename = "timelinePath")]
     timeline_path: Option<Vec<TimelinePath>>,
-    activity: Option<Activity>,
+    activity: Option<JsonActivity>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -368,9 +490,17 @@ struct TimelinePath {
 }
 
 #[derive(Deserialize, Debug)]
-struct Activity {
+struct JsonActivity {
     start: GeoLocation,
     end: GeoLocation,
+    #[serde(rename = "topCandidate")]
+    top_candidate: Option<ActivityCandidate>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ActivityCandidate {
+    #[serde(rename = "type")]
+    candidate_type: String,
 }
 
 enum EntryType {
@@ -398,7 +528,7 @@ impl JsonEntry {
             Some(visit) => JsonEntry::parse_geolocation(&visit.top_candidate.place_location)?,
             None => return Err(DecoderError::EmptyEntryError(format!("Entry {:?} classified as Visit but was empty", self.start_time))),
         };
-        let point = SpaceTimePoint{start_time, end_time, latitude: geo_location.0, longitude: geo_location.1};
+        let point = SpaceTimePoint{start_time, end_time, latitude: geo_location.0, longitude: geo_location.1, accuracy: None, activity: Some(Activity::Stationary), utc_offset_secs: source_utc_offset(&self.start_time)};
         Ok(vec![point])
     }
 
@@ -422,7 +552,7 @@ impl JsonEntry {
                     let start_time_minutes_offset: i64 = timeline_point.duration_minutes_offset_from_start_time.parse()?;
                     path_start_time + Duration::minutes(start_time_minutes_offset)
                 };
-            space_time_points.push(SpaceTimePoint{start_time: last_point_end_time, end_time: point_end_time, latitude: geo_location.0, longitude: geo_location.1});
+            space_time_points.push(SpaceTimePoint{start_time: last_point_end_time, end_time: point_end_time, latitude: geo_location.0, longitude: geo_location.1, accuracy: None, activity: None, utc_offset_secs: source_utc_offset(&self.start_time)});
             last_point_end_time = point_end_time;
         }
         Ok(space_time_points)
@@ -433,13 +563,17 @@ impl JsonEntry {
         let activity_end_time = parse_timestamp_str(&self.end_time)?;
         let activity_mid_time = activity_start_time + (activity_end_time - activity_start_time) / 2;
 
+        let activity = self.activity.as_ref()
+            .and_then(|activity| activity.top_candidate.as_ref())
+            .and_then(|top_candidate| Activity::from_description(&top_candidate.candidate_type));
         let (start_geo_location, end_geo_location) = match self.activity.as_ref() {
             Some(activity) => (JsonEntry::parse_geolocation(&activity.start)?, JsonEntry::parse_geolocation(&activity.end)?),
             None => return Err(DecoderError::EmptyEntryError(format!("Entry {:?} classified as StartEnd Entry but was empty", self.start_time))),
         };
 
-        let start_point = SpaceTimePoint{start_time: activity_start_time, end_time: activity_mid_time, latitude: start_geo_location.0, longitude: start_geo_location.1};
-        let end_point = SpaceTimePoint{start_time: activity_mid_time, end_time: activity_end_time, latitude: end_geo_location.0, longitude: end_geo_location.1};
+        let utc_offset_secs = source_utc_offset(&self.start_time);
+        let start_point = SpaceTimePoint{start_time: activity_start_time, end_time: activity_mid_time, latitude: start_geo_location.0, longitude: start_geo_location.1, accuracy: None, activity, utc_offset_secs};
+        let end_point = SpaceTimePoint{start_time: activity_mid_time, end_time: activity_end_time, latitude: end_geo_location.0, longitude: end_geo_location.1, accuracy: None, activity, utc_offset_secs};
         Ok(vec![start_point, end_point])
     }
 
@@ -463,9 +597,8 @@ impl JsonEntry {
         if parts.len() == 2 {
             let latitude = parts[0].trim_start_matches("geo:").parse::<f64>().ok();
             let longitude = parts[1].parse::<f64>().ok();
-            match (latitude, longitude) {
-                (Some(lat), Some(lon)) => return Ok((lat, lon)),
-                _ => ()
+            if let (Some(lat), Some(lon)) = (latitude, longitude) {
+                return Ok((lat, lon));
             }
         }
         Err(DecoderError::GeoParseError(format!("Unable to parse location string {:?}", geolocation)))
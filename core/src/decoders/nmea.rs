@@ -0,0 +1,269 @@
+use super::*;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+
+/// Fixes read from an NMEA 0183 log, along with any sentences that had to be skipped
+#[derive(Debug)]
+pub struct NmeaLog {
+    fixes: Vec<NmeaFix>,
+    pub warnings: Vec<DecoderWarning>,
+}
+
+#[derive(Debug)]
+struct NmeaFix {
+    timestamp: DateTime<Utc>,
+    latitude: f64,
+    longitude: f64,
+}
+
+enum Sentence {
+    /// Recommended minimum data, the only sentence carrying the date
+    Rmc { date: NaiveDate, time: NaiveTime, position: Option<(f64, f64)> },
+    /// Fix data, carries the time of day but no date
+    Gga { time: NaiveTime, position: Option<(f64, f64)> },
+    Other,
+}
+
+impl NmeaLog {
+    fn push_fix(&mut self, fix: NmeaFix, line: usize) {
+        match self.fixes.last() {
+            // RMC and GGA sentences of the same epoch describe the same fix
+            Some(last) if last.timestamp == fix.timestamp => (),
+            Some(last) if last.timestamp > fix.timestamp => self.warnings.push(DecoderWarning {
+                line,
+                message: format!("Fix at {} is earlier than the previous fix at {}", fix.timestamp, last.timestamp)
+            }),
+            _ => self.fixes.push(fix),
+        }
+    }
+}
+
+impl FromStr for NmeaLog {
+    type Err = DecoderError;
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut log = NmeaLog { fixes: Vec::new(), warnings: Vec::new() };
+        let mut date: Option<NaiveDate> = None;
+        let mut last_time: Option<NaiveTime> = None;
+        // GGA sentences seen before the first RMC have to wait for a date
+        let mut undated: Vec<(usize, NaiveTime, (f64, f64))> = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let line_number = i + 1;
+            // Loggers often prefix sentences with their own timestamps, so start reading at the '$'
+            let sentence = match line.find('$') {
+                Some(start) => line[start..].trim_end(),
+                None => continue,
+            };
+
+            let sentence = match parse_sentence(sentence) {
+                Ok(sentence) => sentence,
+                Err(err) => {
+                    log.warnings.push(DecoderWarning { line: line_number, message: err.to_string() });
+                    continue;
+                }
+            };
+
+            match sentence {
+                Sentence::Rmc { date: rmc_date, time, position } => {
+                    if date.is_none() {
+                        for (undated_line, undated_time, (latitude, longitude)) in undated.drain(..) {
+                            // A fix later in the day than the first RMC was made before midnight
+                            let undated_date = match undated_time > time {
+                                true => rmc_date.pred_opt().unwrap_or(rmc_date),
+                                false => rmc_date,
+                            };
+                            let timestamp = undated_date.and_time(undated_time).and_utc();
+                            log.push_fix(NmeaFix { timestamp, latitude, longitude }, undated_line);
+                        }
+                    }
+                    date = Some(rmc_date);
+                    last_time = Some(time);
+                    if let Some((latitude, longitude)) = position {
+                        log.push_fix(NmeaFix { timestamp: rmc_date.and_time(time).and_utc(), latitude, longitude }, line_number);
+                    }
+                },
+                Sentence::Gga { time, position } => {
+                    // Carry the date over midnight until the next RMC sentence confirms it
+                    if let (Some(current_date), Some(previous_time)) = (date, last_time) {
+                        if previous_time - time > Duration::hours(12) {
+                            date = current_date.succ_opt();
+                        }
+                    }
+                    last_time = Some(time);
+                    match (date, position) {
+                        (Some(date), Some((latitude, longitude))) => log.push_fix(NmeaFix { timestamp: date.and_time(time).and_utc(), latitude, longitude }, line_number),
+                        (None, Some(position)) => undated.push((line_number, time, position)),
+                        (_, None) => (),
+                    }
+                },
+                Sentence::Other => (),
+            }
+        }
+
+        for (line, _, _) in undated {
+            log.warnings.push(DecoderWarning { line, message: "GGA fix has no date, log contains no RMC sentence".to_string() });
+        }
+
+        Ok(log)
+    }
+}
+
+/// NMEA 0183 sentence logs from GPS loggers and dashcams
+pub struct NmeaDecoder;
+
+impl Decoder for NmeaDecoder {
+    fn name(&self) -> &str {
+        "NMEA 0183"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        let head = sniff_head(content);
+        let sentence_types: Vec<&str> = head.lines()
+            .filter_map(|line| line.find('$').and_then(|start| line.get(start + 3..start + 6)))
+            .collect();
+        if sentence_types.iter().any(|sentence_type| *sentence_type == "RMC" || *sentence_type == "GGA") {
+            Confidence::High
+        } else if !sentence_types.is_empty() {
+            Confidence::Low
+        } else {
+            Confidence::None
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let mut nmea_log = NmeaLog::from_str(as_text(content)?)?;
+        let warnings = std::mem::take(&mut nmea_log.warnings);
+        Ok(SpaceTimeRecord::new(nmea_log.into_space_time_points(filter)?, warnings))
+    }
+}
+
+impl NmeaLog {
+    fn into_space_time_points(self, filter: &PointFilter) -> PointsResult {
+        let mut space_time_points = Vec::with_capacity(self.fixes.len());
+        for (i, fix) in self.fixes.iter().enumerate() {
+            let end_time = match self.fixes.get(i + 1) {
+                Some(next_fix) => next_fix.timestamp,
+                None => fix.timestamp // Use the same time for the last point
+            };
+            let point = SpaceTimePoint {
+                start_time: fix.timestamp,
+                end_time,
+                latitude: fix.latitude,
+                longitude: fix.longitude,
+                accuracy: None,
+                activity: None,
+                utc_offset_secs: None,
+            };
+            if filter.matches(&point) {
+                space_time_points.push(point);
+            }
+        }
+        Ok(space_time_points)
+    }
+}
+
+/// Validate the checksum of a sentence and parse the sentence types we use
+/// example: "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A"
+fn parse_sentence(sentence: &str) -> Result<Sentence, DecoderError> {
+    let (body, checksum) = match sentence.trim_start_matches('$').split_once('*') {
+        Some((body, checksum)) => (body, checksum),
+        None => return Err(DecoderError::DeserializeError(format!("Sentence {:?} is missing a checksum", sentence))),
+    };
+    let expected = u8::from_str_radix(checksum, 16)
+        .map_err(|_| DecoderError::DeserializeError(format!("Sentence {:?} has a malformed checksum", sentence)))?;
+    let actual = body.bytes().fold(0u8, |checksum, byte| checksum ^ byte);
+    if actual != expected {
+        return Err(DecoderError::DeserializeError(format!("Sentence {:?} failed checksum, expected {:02X} but calculated {:02X}", sentence, expected, actual)));
+    }
+
+    let fields: Vec<&str> = body.split(',').collect();
+    // Sentence addresses are a two letter talker id (GP, GN, GL, ...) followed by the sentence type
+    let sentence_type = fields[0].get(2..).unwrap_or_default();
+    match sentence_type {
+        "RMC" if fields.len() >= 10 => {
+            let time = parse_time(fields[1])?;
+            let date = NaiveDate::parse_from_str(fields[9], "%d%m%y")
+                .map_err(|err| DecoderError::TimeParseError(format!("Invalid RMC date {:?}: {}", fields[9], err)))?;
+            // Status 'V' means the receiver had no valid fix
+            let position = match fields[2] {
+                "A" => Some((parse_coordinate(fields[3], fields[4])?, parse_coordinate(fields[5], fields[6])?)),
+                _ => None,
+            };
+            Ok(Sentence::Rmc { date, time, position })
+        },
+        "GGA" if fields.len() >= 7 => {
+            let time = parse_time(fields[1])?;
+            // Fix quality 0 means the receiver had no valid fix
+            let position = match fields[6] {
+                "" | "0" => None,
+                _ => Some((parse_coordinate(fields[2], fields[3])?, parse_coordinate(fields[4], fields[5])?)),
+            };
+            Ok(Sentence::Gga { time, position })
+        },
+        "RMC" | "GGA" => Err(DecoderError::DeserializeError(format!("Sentence {:?} has too few fields", sentence))),
+        _ => Ok(Sentence::Other),
+    }
+}
+
+/// Parse NMEA time of day
+/// example: "123519.00" -> 12:35:19
+fn parse_time(time: &str) -> Result<NaiveTime, DecoderError> {
+    NaiveTime::parse_from_str(time, "%H%M%S%.f")
+        .map_err(|err| DecoderError::TimeParseError(format!("Invalid NMEA time {:?}: {}", time, err)))
+}
+
+/// Convert NMEA ddmm.mmmm (or dddmm.mmmm for longitudes) to decimal degrees
+/// example: "4807.038", "N" -> 48.1173
+fn parse_coordinate(value: &str, hemisphere: &str) -> Result<f64, DecoderError> {
+    let invalid = || DecoderError::GeoParseError(format!("Unable to parse NMEA coordinate {:?} {:?}", value, hemisphere));
+    // The minutes always take the two digits before the decimal point
+    let minutes_start = value.find('.').unwrap_or(value.len()).checked_sub(2).ok_or_else(invalid)?;
+    // Sliced with get as a multibyte character could put the offset inside it
+    let degrees: f64 = value.get(..minutes_start).ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+    let minutes: f64 = value.get(minutes_start..).ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+    if minutes >= 60.0 {
+        return Err(invalid());
+    }
+
+    let coordinate = degrees + minutes / 60.0;
+    match hemisphere {
+        "N" | "E" => Ok(coordinate),
+        "S" | "W" => Ok(-coordinate),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coordinate() {
+        assert!((parse_coordinate("4807.038", "N").unwrap() - 48.1173).abs() < 1e-9);
+        assert!((parse_coordinate("01131.000", "W").unwrap() + 11.516666666).abs() < 1e-6);
+        assert!(parse_coordinate("4807.038", "X").is_err());
+        assert!(parse_coordinate("7", "N").is_err());
+        assert!(parse_coordinate("1é.5", "N").is_err());
+    }
+
+    #[test]
+    fn test_parse_sentence_checksum() {
+        assert!(matches!(parse_sentence("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A"), Ok(Sentence::Rmc { .. })));
+        assert!(parse_sentence("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6B").is_err());
+        assert!(parse_sentence("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W").is_err());
+    }
+
+    #[test]
+    fn test_undated_fix_before_midnight() {
+        let log = NmeaLog::from_str("\
+$GPGGA,235958,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*4A
+$GPRMC,000001,A,4807.100,N,01131.100,E,022.4,084.4,240394,003.1,W*6A
+").unwrap();
+        let timestamps: Vec<String> = log.fixes.iter().map(|fix| fix.timestamp.to_rfc3339()).collect();
+        assert_eq!(timestamps, ["1994-03-23T23:59:58+00:00", "1994-03-24T00:00:01+00:00"]);
+        assert!(log.warnings.is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use super::*;
+use serde::Deserialize;
+use chrono::{DateTime, Utc};
+
+/// Locations from Overland GeoJSON batches, either one batch/feature per line or a single document
+#[derive(Debug)]
+pub struct OverlandLog {
+    locations: Vec<OverlandLocation>,
+    pub warnings: Vec<DecoderWarning>,
+}
+
+#[derive(Debug)]
+struct OverlandLocation {
+    timestamp: DateTime<Utc>,
+    latitude: f64,
+    longitude: f64,
+    accuracy: Option<f64>,
+    utc_offset_secs: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Geometry,
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+struct Geometry {
+    #[serde(rename = "type")]
+    geometry_type: String,
+    coordinates: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct Properties {
+    timestamp: String,
+    horizontal_accuracy: Option<f64>,
+}
+
+impl OverlandLog {
+    /// Read a batch, a feature collection or a single feature. Each feature is read on its own, so a bad one only
+    /// costs a warning
+    fn push_document(&mut self, mut document: serde_json::Value, line: usize) {
+        let features = ["locations", "features"].into_iter()
+            .find_map(|key| document.get_mut(key).and_then(serde_json::Value::as_array_mut).map(std::mem::take));
+        let features = features.unwrap_or_else(|| vec![document]);
+        for feature in features {
+            let location = serde_json::from_value::<Feature>(feature).map_err(DecoderError::from).and_then(|feature| feature.to_location());
+            match location {
+                Ok(Some(location)) => self.locations.push(location),
+                Ok(None) => (),
+                Err(err) => self.warnings.push(DecoderWarning { line, message: err.to_string() }),
+            }
+        }
+    }
+}
+
+impl Feature {
+    fn to_location(&self) -> Result<Option<OverlandLocation>, DecoderError> {
+        // Trip summaries and other non-point features don't describe a single fix
+        if self.geometry.geometry_type != "Point" {
+            return Ok(None);
+        }
+        // GeoJSON coordinates are [longitude, latitude]
+        match self.geometry.coordinates.as_slice() {
+            [longitude, latitude, ..] => Ok(Some(OverlandLocation {
+                timestamp: DateTime::parse_from_rfc3339(&self.properties.timestamp)?.with_timezone(&Utc),
+                latitude: *latitude,
+                longitude: *longitude,
+                accuracy: self.properties.horizontal_accuracy,
+                utc_offset_secs: source_utc_offset(&self.properties.timestamp),
+            })),
+            _ => Err(DecoderError::GeoParseError(format!("Point feature at {} has too few coordinates", self.properties.timestamp)))
+        }
+    }
+}
+
+impl FromStr for OverlandLog {
+    type Err = DecoderError;
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut log = OverlandLog { locations: Vec::new(), warnings: Vec::new() };
+
+        match serde_json::from_str::<serde_json::Value>(content) {
+            Ok(document) => log.push_document(document, 1),
+            // Not a single JSON value, so read it as JSON-lines
+            Err(_) => for (i, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<serde_json::Value>(line) {
+                    Ok(document) => log.push_document(document, i + 1),
+                    Err(err) => log.warnings.push(DecoderWarning { line: i + 1, message: DecoderError::from(err).to_string() }),
+                }
+            }
+        }
+
+        // Batches are uploaded whenever the phone gets around to it, so they can arrive out of order
+        log.locations.sort_by_key(|location| location.timestamp);
+        log.locations.dedup_by_key(|location| location.timestamp);
+        Ok(log)
+    }
+}
+
+/// Overland GeoJSON location batches
+pub struct OverlandDecoder;
+
+impl Decoder for OverlandDecoder {
+    fn name(&self) -> &str {
+        "Overland"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        let head = sniff_head(content);
+        match head.contains("\"Feature\"") && head.contains("\"geometry\"") {
+            true => Confidence::High,
+            false => Confidence::None
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let mut overland_log = OverlandLog::from_str(as_text(content)?)?;
+        let warnings = std::mem::take(&mut overland_log.warnings);
+        Ok(SpaceTimeRecord::new(overland_log.into_space_time_points(filter)?, warnings))
+    }
+}
+
+impl OverlandLog {
+    fn into_space_time_points(self, filter: &PointFilter) -> PointsResult {
+        let mut space_time_points = Vec::with_capacity(self.locations.len());
+        for (i, location) in self.locations.iter().enumerate() {
+            let end_time = match self.locations.get(i + 1) {
+                Some(next_location) => next_location.timestamp,
+                None => location.timestamp // Use the same time for the last point
+            };
+            let point = SpaceTimePoint {
+                start_time: location.timestamp,
+                end_time,
+                latitude: location.latitude,
+                longitude: location.longitude,
+                accuracy: location.accuracy,
+                activity: None,
+                utc_offset_secs: location.utc_offset_secs,
+            };
+            if filter.matches(&point) {
+                space_time_points.push(point);
+            }
+        }
+        Ok(space_time_points)
+    }
+}
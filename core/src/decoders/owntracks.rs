@@ -0,0 +1,129 @@
+use super::*;
+use serde::Deserialize;
+use chrono::{DateTime, Utc};
+
+/// Locations from an OwnTracks recorder `.rec` file or a JSON-lines/JSON array export
+#[derive(Debug)]
+pub struct OwnTracksLog {
+    locations: Vec<OwnTracksLocation>,
+    pub warnings: Vec<DecoderWarning>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OwnTracksMessage {
+    #[serde(rename = "_type")]
+    message_type: String,
+    #[serde(flatten)]
+    location: Option<OwnTracksLocation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OwnTracksLocation {
+    tst: i64,
+    lat: f64,
+    lon: f64,
+    acc: Option<f64>,
+}
+
+impl OwnTracksLog {
+    fn push_message(&mut self, message: OwnTracksMessage, line: usize) {
+        // Transitions, waypoints, last will messages etc. carry no fix of their own
+        if message.message_type != "location" {
+            return;
+        }
+        match message.location {
+            Some(location) => self.locations.push(location),
+            None => self.warnings.push(DecoderWarning { line, message: "Location message is missing tst, lat or lon".to_string() }),
+        }
+    }
+}
+
+impl FromStr for OwnTracksLog {
+    type Err = DecoderError;
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut log = OwnTracksLog { locations: Vec::new(), warnings: Vec::new() };
+
+        if content.trim_start().starts_with('[') {
+            let messages: Vec<OwnTracksMessage> = serde_json::from_str(content)?;
+            for (i, message) in messages.into_iter().enumerate() {
+                log.push_message(message, i + 1);
+            }
+        } else {
+            for (i, line) in content.lines().enumerate() {
+                // .rec lines are "<timestamp>\t<topic>\t<json>", JSON-lines exports are just the json
+                let json = match line.find('{') {
+                    Some(start) => &line[start..],
+                    None => continue,
+                };
+                match serde_json::from_str::<OwnTracksMessage>(json) {
+                    Ok(message) => log.push_message(message, i + 1),
+                    Err(err) => log.warnings.push(DecoderWarning { line: i + 1, message: DecoderError::from(err).to_string() }),
+                }
+            }
+        }
+
+        // Recorder files are appended to as messages arrive, which isn't always in order
+        log.locations.sort_by_key(|location| location.tst);
+        log.locations.dedup_by_key(|location| location.tst);
+        Ok(log)
+    }
+}
+
+impl OwnTracksLocation {
+    fn get_timestamp(&self) -> Result<DateTime<Utc>, DecoderError> {
+        DateTime::from_timestamp(self.tst, 0)
+            .ok_or_else(|| DecoderError::TimeParseError(format!("Error converting tst {} to DateTime", self.tst)))
+    }
+}
+
+/// OwnTracks recorder and JSON exports
+pub struct OwnTracksDecoder;
+
+impl Decoder for OwnTracksDecoder {
+    fn name(&self) -> &str {
+        "OwnTracks"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        match sniff_head(content).contains("\"_type\"") {
+            true => Confidence::High,
+            false => Confidence::None
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let mut owntracks_log = OwnTracksLog::from_str(as_text(content)?)?;
+        let warnings = std::mem::take(&mut owntracks_log.warnings);
+        Ok(SpaceTimeRecord::new(owntracks_log.into_space_time_points(filter)?, warnings))
+    }
+}
+
+impl OwnTracksLog {
+    fn into_space_time_points(self, filter: &PointFilter) -> PointsResult {
+        let mut space_time_points = Vec::with_capacity(self.locations.len());
+        for (i, location) in self.locations.iter().enumerate() {
+            let start_time = location.get_timestamp()?;
+            let end_time = match self.locations.get(i + 1) {
+                Some(next_location) => next_location.get_timestamp()?,
+                None => start_time // Use the same time for the last point
+            };
+            let point = SpaceTimePoint {
+                start_time,
+                end_time,
+                latitude: location.lat,
+                longitude: location.lon,
+                accuracy: location.acc,
+                activity: None,
+                utc_offset_secs: None,
+            };
+            if filter.matches(&point) {
+                space_time_points.push(point);
+            }
+        }
+        Ok(space_time_points)
+    }
+}
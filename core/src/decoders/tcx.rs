@@ -0,0 +1,133 @@
+use super::*;
+use crate::model;
+use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use quick_xml::de;
+
+/// Garmin Training Center (TCX) activity file
+#[derive(Debug, Deserialize)]
+pub struct TcxRecords {
+    #[serde(rename = "Activities", default)]
+    activities: Option<Activities>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Activities {
+    #[serde(rename = "Activity", default)]
+    activity: Vec<Activity>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Activity {
+    #[serde(rename = "@Sport")]
+    sport: Option<String>,
+    #[serde(rename = "Lap", default)]
+    lap: Vec<Lap>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Lap {
+    #[serde(rename = "Track", default)]
+    track: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Track {
+    #[serde(rename = "Trackpoint", default)]
+    trackpoint: Vec<Trackpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Trackpoint {
+    #[serde(rename = "Time")]
+    time: String,
+    #[serde(rename = "Position")]
+    position: Option<Position>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Position {
+    #[serde(rename = "LatitudeDegrees")]
+    latitude_degrees: f64,
+    #[serde(rename = "LongitudeDegrees")]
+    longitude_degrees: f64,
+}
+
+/// Garmin Training Center activities
+pub struct TcxDecoder;
+
+impl Decoder for TcxDecoder {
+    fn name(&self) -> &str {
+        "TCX"
+    }
+
+    fn sniff(&self, content: &[u8]) -> Confidence {
+        match sniff_head(content).contains("<TrainingCenterDatabase") {
+            true => Confidence::High,
+            false => Confidence::None
+        }
+    }
+
+    fn decode(&self, content: &[u8]) -> RecordResult {
+        self.decode_filtered(content, &PointFilter::default())
+    }
+
+    fn decode_filtered(&self, content: &[u8], filter: &PointFilter) -> RecordResult {
+        let points = TcxRecords::from_str(as_text(content)?)?.into_space_time_points(filter)?;
+        Ok(SpaceTimeRecord::new(points, Vec::new()))
+    }
+}
+
+impl TcxRecords {
+    fn into_space_time_points(self, filter: &PointFilter) -> PointsResult {
+        let mut space_time_points = Vec::new();
+        for activity in self.activities.iter().flat_map(|activities| &activities.activity) {
+            let sport = activity.sport.as_deref().and_then(model::Activity::from_description);
+            for lap in &activity.lap {
+                for track in &lap.track {
+                    space_time_points.extend(track.to_space_time_points(sport, filter)?);
+                }
+            }
+        }
+        Ok(space_time_points)
+    }
+}
+
+impl Track {
+    fn to_space_time_points(&self, sport: Option<model::Activity>, filter: &PointFilter) -> PointsResult {
+        // Trackpoints without a position (e.g. heart rate only samples) can't be placed in space
+        let trackpoints: Vec<(&Trackpoint, &Position)> = self.trackpoint.iter()
+            .filter_map(|trackpoint| trackpoint.position.as_ref().map(|position| (trackpoint, position)))
+            .collect();
+
+        let mut points = Vec::with_capacity(trackpoints.len());
+        for (i, (trackpoint, position)) in trackpoints.iter().enumerate() {
+            let start_time: DateTime<Utc> = trackpoint.time.parse()?;
+            let end_time = match trackpoints.get(i + 1) {
+                Some((next_trackpoint, _)) => next_trackpoint.time.parse()?,
+                None => start_time // Use the same time for the last point
+            };
+
+            let point = SpaceTimePoint {
+                start_time,
+                end_time,
+                latitude: position.latitude_degrees,
+                longitude: position.longitude_degrees,
+                accuracy: None,
+                activity: sport,
+                utc_offset_secs: source_utc_offset(&trackpoint.time),
+            };
+            if filter.matches(&point) {
+                points.push(point);
+            }
+        }
+        Ok(points)
+    }
+}
+
+impl FromStr for TcxRecords {
+    type Err = DecoderError;
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        Ok(de::from_str(content)?)
+    }
+}
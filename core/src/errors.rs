@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+use crate::decoders::errors::DecoderError;
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Error {
+    DecoderError(DecoderError),
+    /// Tokens from someone else that can't be compared with our own
+    TokenError(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::DecoderError(err) => write!(f, "Decoder error: {}", err),
+            Error::TokenError(msg) => write!(f, "Token error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<DecoderError> for Error {
+    fn from(err: DecoderError) -> Error {
+        Error::DecoderError(err)
+    }
+}
@@ -0,0 +1,117 @@
+//! Leaving out places two people share every day, like a home or office, so they don't drown out the encounters elsewhere
+
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::{model::{SpaceTimePoint, SpaceTimeRecord}, places::{frequent_places, Place, PLACE_RADIUS_M}};
+
+/// Radius given to new zones, wide enough to cover a building and GPS drift
+pub const DEFAULT_ZONE_RADIUS_M: f64 = 150.0;
+
+/// A circle whose points are dropped before matching
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExclusionZone {
+    pub label: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub radius_m: f64,
+}
+
+impl ExclusionZone {
+    pub fn contains(&self, point: &SpaceTimePoint) -> bool {
+        point.haversine_distance(self.latitude, self.longitude) * 1000.0 <= self.radius_m
+    }
+}
+
+/// Parses "lat,lon" or "lat,lon,radius_m", with the default radius when it isn't given
+impl FromStr for ExclusionZone {
+    type Err = String;
+
+    fn from_str(zone: &str) -> Result<Self, Self::Err> {
+        let numbers = zone.split(',')
+            .map(|number| number.trim().parse::<f64>().map_err(|err| format!("Invalid number {:?}: {}", number.trim(), err)))
+            .collect::<Result<Vec<f64>, String>>()?;
+        let (latitude, longitude, radius_m) = match numbers.as_slice() {
+            [latitude, longitude] => (*latitude, *longitude, DEFAULT_ZONE_RADIUS_M),
+            [latitude, longitude, radius_m] if *radius_m > 0.0 => (*latitude, *longitude, *radius_m),
+            [_, _, _] => return Err("A zone's radius should be more than 0".to_string()),
+            _ => return Err("A zone should be latitude,longitude or latitude,longitude,radius_m".to_string()),
+        };
+        Ok(ExclusionZone { label: format!("{:.5}, {:.5}", latitude, longitude), latitude, longitude, radius_m })
+    }
+}
+
+impl From<&Place> for ExclusionZone {
+    fn from(place: &Place) -> Self {
+        ExclusionZone {
+            label: place.kind.to_string(),
+            latitude: place.latitude,
+            longitude: place.longitude,
+            radius_m: PLACE_RADIUS_M,
+        }
+    }
+}
+
+/// A copy of the record without the points inside any of the zones
+pub fn without_exclusion_zones(record: &SpaceTimeRecord, zones: &[ExclusionZone]) -> SpaceTimeRecord {
+    let points = record.points.iter()
+        .filter(|point| !zones.iter().any(|zone| zone.contains(point)))
+        .cloned()
+        .collect();
+    SpaceTimeRecord::new(points, record.warnings.clone())
+}
+
+/// Zones around the places someone spends the most time at, labelled home or work where they look like it
+pub fn suggest_exclusion_zones(record: &SpaceTimeRecord, max_zones: usize) -> Vec<ExclusionZone> {
+    frequent_places(record, max_zones).iter().map(ExclusionZone::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{test_point, Activity};
+
+    fn point(start: i64, latitude: f64, activity: Option<Activity>) -> SpaceTimePoint {
+        SpaceTimePoint { activity, ..test_point(start, start + 3600, latitude, -75.0) }
+    }
+
+    #[test]
+    fn test_without_exclusion_zones() {
+        let record = SpaceTimeRecord::new(vec![point(0, 40.0, None), point(3600, 40.0005, None), point(7200, 40.01, None)], Vec::new());
+        let zone = ExclusionZone { label: "Office".to_string(), latitude: 40.0, longitude: -75.0, radius_m: 100.0 };
+        let filtered = without_exclusion_zones(&record, &[zone]);
+        assert_eq!(filtered.points.len(), 1);
+        assert_eq!(filtered.points[0].latitude, 40.01);
+        assert_eq!(without_exclusion_zones(&record, &[]).points.len(), 3);
+    }
+
+    #[test]
+    fn test_exclusion_zone_from_str() {
+        let zone: ExclusionZone = "40.1, -75.2, 200".parse().unwrap();
+        assert_eq!((zone.latitude, zone.longitude, zone.radius_m), (40.1, -75.2, 200.0));
+        assert_eq!("40.1,-75.2".parse::<ExclusionZone>().unwrap().radius_m, DEFAULT_ZONE_RADIUS_M);
+        assert!("40.1,-75.2,0".parse::<ExclusionZone>().is_err());
+        assert!("40.1".parse::<ExclusionZone>().is_err());
+        assert!("40.1,abc".parse::<ExclusionZone>().is_err());
+    }
+
+    #[test]
+    fn test_suggest_exclusion_zones() {
+        let mut points = Vec::new();
+        for day in 0..7 {
+            points.push(point(day * 86400, 40.0 + day as f64 * 0.00001, Some(Activity::Stationary)));
+            points.push(point(day * 86400 + 3600, 40.05, Some(Activity::Walking)));
+            if day < 2 {
+                points.push(point(day * 86400 + 7200, 40.1, Some(Activity::Stationary)));
+            }
+        }
+        let record = SpaceTimeRecord::new(points, Vec::new());
+
+        let zones = suggest_exclusion_zones(&record, 3);
+        // Walking points aren't stays and not enough time was spent at the second place
+        assert_eq!(zones.len(), 1);
+        assert!((zones[0].latitude - 40.00003).abs() < 1e-9);
+        assert_eq!(zones[0].label, "Frequent place");
+        assert_eq!(zones[0].radius_m, PLACE_RADIUS_M);
+        assert!(suggest_exclusion_zones(&record, 0).is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+//! Writing encounters out as CSV, JSON, GeoJSON or iCalendar for saving and sharing, and a record's points as CSV,
+//! JSON or GeoJSON
+
+use std::io::{self, Write};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::json;
+use crate::{compute::Encounter, model::SpaceTimePoint};
+
+/// An encounter along with who it was between
+#[derive(Serialize)]
+pub struct EncounterRow<'a> {
+    pub person1: &'a str,
+    pub person2: &'a str,
+    #[serde(flatten)]
+    pub encounter: Encounter,
+    /// Each person's points around the encounter, drawn as lines in GeoJSON
+    #[serde(skip)]
+    pub track1: &'a [SpaceTimePoint],
+    #[serde(skip)]
+    pub track2: &'a [SpaceTimePoint],
+}
+
+impl<'a> EncounterRow<'a> {
+    /// A row without tracks
+    pub fn new(person1: &'a str, person2: &'a str, encounter: Encounter) -> Self {
+        EncounterRow { person1, person2, encounter, track1: &[], track2: &[] }
+    }
+}
+
+pub fn write_json(out: &mut impl Write, rows: &[EncounterRow]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, rows)?;
+    writeln!(out)
+}
+
+pub fn write_csv(out: &mut impl Write, rows: &[EncounterRow]) -> io::Result<()> {
+    writeln!(out, "person1,person2,start_time,end_time,duration_secs,min_distance_m,latitude,longitude,matches,activity1,activity2,score")?;
+    for row in rows {
+        writeln!(out, "{},{},{},{},{},{:.1},{},{},{},{},{},{:.1}",
+            escape_csv(row.person1),
+            escape_csv(row.person2),
+            row.encounter.start_time.to_rfc3339(),
+            row.encounter.end_time.to_rfc3339(),
+            row.encounter.duration().num_seconds(),
+            row.encounter.min_distance_km * 1000.0,
+            row.encounter.latitude,
+            row.encounter.longitude,
+            row.encounter.matches,
+            row.encounter.activity1.map(|activity| activity.to_string()).unwrap_or_default(),
+            row.encounter.activity2.map(|activity| activity.to_string()).unwrap_or_default(),
+            row.encounter.score)?;
+    }
+    Ok(())
+}
+
+/// A FeatureCollection with a point at the closest approach of each encounter
+/// and a line for each person's track around it
+pub fn write_geojson(out: &mut impl Write, rows: &[EncounterRow]) -> io::Result<()> {
+    let mut features = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        let mut properties = serde_json::to_value(row)?;
+        properties["encounter"] = json!(index);
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": [row.encounter.longitude, row.encounter.latitude] },
+            "properties": properties,
+        }));
+        for (person, track) in [(row.person1, row.track1), (row.person2, row.track2)] {
+            // A LineString needs at least two positions
+            if track.len() < 2 {
+                continue;
+            }
+            let coordinates: Vec<[f64; 2]> = track.iter().map(|point| [point.longitude, point.latitude]).collect();
+            features.push(json!({
+                "type": "Feature",
+                "geometry": { "type": "LineString", "coordinates": coordinates },
+                "properties": {
+                    "encounter": index,
+                    "person": person,
+                    "start_time": track[0].start_time,
+                    "end_time": track[track.len() - 1].end_time,
+                },
+            }));
+        }
+    }
+    serde_json::to_writer_pretty(&mut *out, &json!({ "type": "FeatureCollection", "features": features }))?;
+    writeln!(out)
+}
+
+/// An iCalendar file with one event per encounter
+pub fn write_ics(out: &mut impl Write, rows: &[EncounterRow]) -> io::Result<()> {
+    let stamp = format_ics_time(Utc::now());
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//chance-encounters//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for (index, row) in rows.iter().enumerate() {
+        let encounter = &row.encounter;
+        // Calendars drop events that end when they start
+        let end_time = encounter.end_time.max(encounter.start_time + Duration::minutes(1));
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}-{}@chance-encounters", encounter.start_time.timestamp(), index),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART:{}", format_ics_time(encounter.start_time)),
+            format!("DTEND:{}", format_ics_time(end_time)),
+            format!("SUMMARY:{}", escape_ics(&format!("Encounter: {} and {}", row.person1, row.person2))),
+            format!("LOCATION:{}", escape_ics(&format!("{:.5}, {:.5}", encounter.latitude, encounter.longitude))),
+            format!("GEO:{:.6};{:.6}", encounter.latitude, encounter.longitude),
+            format!("DESCRIPTION:{}", escape_ics(&format!("Closest approach {:.0}m, {} matches, score {:.0}",
+                encounter.min_distance_km * 1000.0, encounter.matches, encounter.score))),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    for line in lines {
+        write!(out, "{}\r\n", fold_ics_line(&line))?;
+    }
+    Ok(())
+}
+
+pub fn write_points_json(out: &mut impl Write, points: &[SpaceTimePoint]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, points)?;
+    writeln!(out)
+}
+
+pub fn write_points_csv(out: &mut impl Write, points: &[SpaceTimePoint]) -> io::Result<()> {
+    writeln!(out, "start_time,end_time,latitude,longitude,accuracy_m,activity,utc_offset_secs")?;
+    for point in points {
+        writeln!(out, "{},{},{},{},{},{},{}",
+            point.start_time.to_rfc3339(),
+            point.end_time.to_rfc3339(),
+            point.latitude,
+            point.longitude,
+            point.accuracy.map(|accuracy| format!("{:.1}", accuracy)).unwrap_or_default(),
+            point.activity.map(|activity| activity.to_string()).unwrap_or_default(),
+            point.utc_offset_secs.map(|offset| offset.to_string()).unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+/// A FeatureCollection with a point for each of a person's points
+pub fn write_points_geojson(out: &mut impl Write, person: &str, points: &[SpaceTimePoint]) -> io::Result<()> {
+    let features: Vec<_> = points.iter()
+        .map(|point| json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": [point.longitude, point.latitude] },
+            "properties": {
+                "person": person,
+                "start_time": point.start_time,
+                "end_time": point.end_time,
+                "accuracy": point.accuracy,
+                "activity": point.activity,
+            },
+        }))
+        .collect();
+    serde_json::to_writer_pretty(&mut *out, &json!({ "type": "FeatureCollection", "features": features }))?;
+    writeln!(out)
+}
+
+/// Format a duration for humans, as shown in result tables
+/// example: 3930 seconds -> "1h 05m"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, (seconds % 3600) / 60),
+    }
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or newline
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_ics_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape an iCalendar TEXT value
+fn escape_ics(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+/// iCalendar lines can be at most 75 bytes, longer ones continue on lines starting with a space
+fn fold_ics_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut line_length = 0;
+    for c in line.chars() {
+        if line_length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            line_length = 1;
+        }
+        folded.push(c);
+        line_length += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_point;
+
+    fn encounter() -> Encounter {
+        Encounter {
+            start_time: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            end_time: DateTime::from_timestamp(1_700_000_600, 0).unwrap(),
+            min_distance_km: 0.012,
+            latitude: 40.0,
+            longitude: -75.0,
+            matches: 3,
+            activity1: None,
+            activity2: None,
+            utc_offset_secs: None,
+            score: 75.0,
+        }
+    }
+
+    fn point(start: i64, latitude: f64) -> SpaceTimePoint {
+        test_point(start, start, latitude, -75.0)
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::seconds(42)), "42s");
+        assert_eq!(format_duration(Duration::seconds(750)), "12m 30s");
+        assert_eq!(format_duration(Duration::seconds(3930)), "1h 05m");
+    }
+
+    #[test]
+    fn test_escape_csv() {
+        assert_eq!(escape_csv("alice"), "alice");
+        assert_eq!(escape_csv("smith, alice"), "\"smith, alice\"");
+        assert_eq!(escape_csv("the \"runner\""), "\"the \"\"runner\"\"\"");
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &[EncounterRow::new("alice", "bob", encounter())]).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], "alice,bob,2023-11-14T22:13:20+00:00,2023-11-14T22:23:20+00:00,600,12.0,40,-75,3,,,75.0");
+    }
+
+    #[test]
+    fn test_write_geojson() {
+        let track = [point(1_700_000_000, 40.0), point(1_700_000_300, 40.001)];
+        let row = EncounterRow { track1: &track, ..EncounterRow::new("alice", "bob", encounter()) };
+        let mut out = Vec::new();
+        write_geojson(&mut out, &[row]).unwrap();
+
+        let geojson: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        // bob's track is empty so only alice gets a line
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0]["geometry"]["coordinates"], json!([-75.0, 40.0]));
+        assert_eq!(features[0]["properties"]["person1"], "alice");
+        assert_eq!(features[1]["geometry"]["type"], "LineString");
+        assert_eq!(features[1]["properties"]["person"], "alice");
+    }
+
+    #[test]
+    fn test_write_points() {
+        let points = [point(1_700_000_000, 40.0), SpaceTimePoint { accuracy: Some(25.0), ..point(1_700_000_300, 40.001) }];
+        let mut out = Vec::new();
+        write_points_csv(&mut out, &points).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(csv.lines().nth(2).unwrap(), "2023-11-14T22:18:20+00:00,2023-11-14T22:18:20+00:00,40.001,-75,25.0,,");
+
+        let mut out = Vec::new();
+        write_points_geojson(&mut out, "alice", &points).unwrap();
+        let geojson: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(geojson["features"].as_array().unwrap().len(), 2);
+        assert_eq!(geojson["features"][1]["geometry"]["coordinates"], json!([-75.0, 40.001]));
+        assert_eq!(geojson["features"][1]["properties"]["person"], "alice");
+
+        let mut out = Vec::new();
+        write_points_json(&mut out, &points).unwrap();
+        let decoded: Vec<SpaceTimePoint> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].accuracy, Some(25.0));
+    }
+
+    #[test]
+    fn test_write_ics() {
+        let mut out = Vec::new();
+        write_ics(&mut out, &[EncounterRow::new("alice", "smith, bob", encounter())]).unwrap();
+        let ics = String::from_utf8(out).unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("DTSTART:20231114T221320Z\r\n"));
+        assert!(ics.contains("DTEND:20231114T222320Z\r\n"));
+        assert!(ics.contains("SUMMARY:Encounter: alice and smith\\, bob\r\n"));
+    }
+
+    #[test]
+    fn test_fold_ics_line() {
+        let line = "DESCRIPTION:".to_string() + &"x".repeat(100);
+        let folded = fold_ics_line(&line);
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), 75);
+        assert!(parts[1].starts_with(' '));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+}
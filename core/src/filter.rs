@@ -0,0 +1,200 @@
+//! Limiting a record to a time range and area while it's decoded, so points outside them are never kept
+
+use std::{fmt, str::FromStr};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{cache::content_hash, model::{SpaceTimePoint, SpaceTimeRecord}};
+
+/// A geographic area points have to be inside
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Area {
+    /// Crosses the antimeridian when `min_longitude` is greater than `max_longitude`
+    BoundingBox { min_latitude: f64, min_longitude: f64, max_latitude: f64, max_longitude: f64 },
+    /// Vertices as (latitude, longitude), implicitly closed
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl Area {
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        match self {
+            Area::BoundingBox { min_latitude, min_longitude, max_latitude, max_longitude } => {
+                let within_longitude = match min_longitude <= max_longitude {
+                    true => (*min_longitude..=*max_longitude).contains(&longitude),
+                    false => longitude >= *min_longitude || longitude <= *max_longitude,
+                };
+                (*min_latitude..=*max_latitude).contains(&latitude) && within_longitude
+            },
+            // Even-odd rule: count the edges a ray going east from the point crosses
+            Area::Polygon(vertices) => {
+                let mut inside = false;
+                for (i, (latitude1, longitude1)) in vertices.iter().enumerate() {
+                    let (latitude2, longitude2) = vertices[(i + 1) % vertices.len()];
+                    if (*latitude1 > latitude) != (latitude2 > latitude) {
+                        let crossing_longitude = longitude1 + (latitude - latitude1) / (latitude2 - latitude1) * (longitude2 - longitude1);
+                        if longitude < crossing_longitude {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            },
+        }
+    }
+}
+
+/// Parses "min_lat,min_lon,max_lat,max_lon" as a bounding box or "lat,lon;lat,lon;lat,lon..." as a polygon
+impl FromStr for Area {
+    type Err = String;
+
+    fn from_str(area: &str) -> Result<Self, Self::Err> {
+        let parse_numbers = |text: &str| text.split(',')
+            .map(|number| number.trim().parse::<f64>().map_err(|err| format!("Invalid coordinate {:?}: {}", number.trim(), err)))
+            .collect::<Result<Vec<f64>, String>>();
+
+        if area.contains(';') {
+            let vertices = area.split(';')
+                .filter(|vertex| !vertex.trim().is_empty())
+                .map(|vertex| match parse_numbers(vertex)?.as_slice() {
+                    [latitude, longitude] => Ok((*latitude, *longitude)),
+                    _ => Err(format!("Polygon vertex {:?} should be latitude,longitude", vertex.trim())),
+                })
+                .collect::<Result<Vec<(f64, f64)>, String>>()?;
+            match vertices.len() {
+                0..=2 => Err("A polygon needs at least 3 vertices".to_string()),
+                _ => Ok(Area::Polygon(vertices)),
+            }
+        } else {
+            match parse_numbers(area)?.as_slice() {
+                [min_latitude, min_longitude, max_latitude, max_longitude] if min_latitude <= max_latitude => Ok(Area::BoundingBox {
+                    min_latitude: *min_latitude,
+                    min_longitude: *min_longitude,
+                    max_latitude: *max_latitude,
+                    max_longitude: *max_longitude,
+                }),
+                [_, _, _, _] => Err("The bounding box's minimum latitude is above its maximum".to_string()),
+                _ => Err("A bounding box should be min_latitude,min_longitude,max_latitude,max_longitude".to_string()),
+            }
+        }
+    }
+}
+
+/// Written the way `from_str` reads it
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Area::BoundingBox { min_latitude, min_longitude, max_latitude, max_longitude } =>
+                write!(f, "{},{},{},{}", min_latitude, min_longitude, max_latitude, max_longitude),
+            Area::Polygon(vertices) => {
+                let vertices: Vec<String> = vertices.iter().map(|(latitude, longitude)| format!("{},{}", latitude, longitude)).collect();
+                write!(f, "{}", vertices.join(";"))
+            },
+        }
+    }
+}
+
+/// Which points to keep when decoding, an empty filter keeps everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PointFilter {
+    /// Points ending before this are dropped
+    pub start_time: Option<DateTime<Utc>>,
+    /// Points starting at or after this are dropped
+    pub end_time: Option<DateTime<Utc>>,
+    pub area: Option<Area>,
+}
+
+impl PointFilter {
+    /// A filter covering whole days, both dates included
+    pub fn from_dates(from: Option<NaiveDate>, to: Option<NaiveDate>, area: Option<Area>) -> Self {
+        PointFilter {
+            start_time: from.map(|from| from.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc()),
+            end_time: to.and_then(|to| to.checked_add_days(Days::new(1))).map(|to| to.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc()),
+            area,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == PointFilter::default()
+    }
+
+    /// Whether anything in the time range could pass, for skipping whole entries before building their points
+    pub fn overlaps(&self, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> bool {
+        self.start_time.is_none_or(|filter_start| end_time >= filter_start)
+            && self.end_time.is_none_or(|filter_end| start_time < filter_end)
+    }
+
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        self.area.as_ref().is_none_or(|area| area.contains(latitude, longitude))
+    }
+
+    pub fn matches(&self, point: &SpaceTimePoint) -> bool {
+        self.overlaps(point.start_time, point.end_time) && self.contains(point.latitude, point.longitude)
+    }
+
+    pub fn apply(&self, record: &mut SpaceTimeRecord) {
+        if !self.is_empty() {
+            record.points.retain(|point| self.matches(point));
+        }
+    }
+
+    /// Identifies a file decoded with this filter, for caching
+    pub fn cache_key(&self, content_hash_value: u64) -> u64 {
+        match self.is_empty() {
+            true => content_hash_value,
+            false => content_hash(format!("{}{:?}", content_hash_value, self).as_bytes()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_area_from_str() {
+        assert_eq!("40,-75,41,-74".parse(), Ok(Area::BoundingBox { min_latitude: 40.0, min_longitude: -75.0, max_latitude: 41.0, max_longitude: -74.0 }));
+        assert_eq!("0,0; 0,1; 1,1;".parse(), Ok(Area::Polygon(vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0)])));
+        assert!("41,-75,40,-74".parse::<Area>().is_err());
+        assert!("0,0;0,1".parse::<Area>().is_err());
+        assert!("40,-75,41".parse::<Area>().is_err());
+        assert!("40,abc,41,-74".parse::<Area>().is_err());
+
+        for area in ["40,-75.5,41,-74", "0,0;0,1;1,1"] {
+            assert_eq!(area.parse::<Area>().unwrap().to_string(), area);
+        }
+    }
+
+    #[test]
+    fn test_area_contains() {
+        let bounding_box: Area = "40,-75,41,-74".parse().unwrap();
+        assert!(bounding_box.contains(40.5, -74.5));
+        assert!(!bounding_box.contains(39.9, -74.5));
+
+        let antimeridian: Area = "-20,170,-10,-170".parse().unwrap();
+        assert!(antimeridian.contains(-15.0, 179.0));
+        assert!(antimeridian.contains(-15.0, -175.0));
+        assert!(!antimeridian.contains(-15.0, 0.0));
+
+        // An L shape, the notch at the top right is outside
+        let polygon: Area = "0,0; 0,2; 1,2; 1,1; 2,1; 2,0".parse().unwrap();
+        assert!(polygon.contains(0.5, 1.5));
+        assert!(polygon.contains(1.5, 0.5));
+        assert!(!polygon.contains(1.5, 1.5));
+        assert!(!polygon.contains(-0.5, 0.5));
+    }
+
+    #[test]
+    fn test_point_filter() {
+        let point = |day: u32, latitude: f64| {
+            let time = NaiveDate::from_ymd_opt(2014, 6, day).unwrap().and_hms_opt(12, 0, 0).unwrap().and_utc();
+            SpaceTimePoint { start_time: time, end_time: time, latitude, longitude: -74.5, accuracy: None, activity: None, utc_offset_secs: None }
+        };
+        let mut record = SpaceTimeRecord::new(vec![point(1, 40.5), point(2, 40.5), point(3, 45.0), point(4, 40.5)], Vec::new());
+        let filter = PointFilter::from_dates(NaiveDate::from_ymd_opt(2014, 6, 2), NaiveDate::from_ymd_opt(2014, 6, 3), Some("40,-75,41,-74".parse().unwrap()));
+        filter.apply(&mut record);
+
+        assert_eq!(record.points.len(), 1);
+        assert_eq!(record.points[0].start_time, point(2, 40.5).start_time);
+        assert_ne!(filter.cache_key(1), 1);
+        assert_eq!(PointFilter::default().cache_key(1), 1);
+    }
+}
@@ -0,0 +1,18 @@
+//! Platform independent analysis engine: decoding location histories and finding encounters between them.
+//! Has no wasm or web dependencies so it can be embedded in other tools.
+
+pub mod decoders;
+pub mod model;
+pub mod compute;
+pub mod timeline;
+pub mod export;
+pub mod cache;
+pub mod filter;
+pub mod exclusion;
+pub mod redaction;
+pub mod cleaning;
+pub mod simplify;
+pub mod places;
+pub mod timezone;
+pub mod tokens;
+pub mod errors;
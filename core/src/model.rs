@@ -0,0 +1,251 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{decoders::errors::DecoderWarning, timezone::utc_offset_at};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceTimeRecord {
+    pub points: Vec<SpaceTimePoint>,
+    pub warnings: Vec<DecoderWarning>,
+}
+
+impl SpaceTimeRecord {
+    pub fn new(points: Vec<SpaceTimePoint>, warnings: Vec<DecoderWarning>) -> Self {
+        debug_assert!(points.windows(2).all(|w| w[0].end_time <= w[1].start_time)); // Ensure points are sorted and don't overlap - removed in release mode
+        SpaceTimeRecord { points, warnings }
+    }
+
+    /// The points that overlap the time range, relies on points being sorted
+    pub fn points_between(&self, start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> &[SpaceTimePoint] {
+        let first = self.points.partition_point(|point| point.end_time < start_time);
+        let last = self.points.partition_point(|point| point.start_time <= end_time);
+        &self.points[first..last.max(first)]
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceTimePoint {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub accuracy: Option<f64>, // horizontal accuracy radius in meters, when the source reports one
+    pub activity: Option<Activity>, // what the person was doing, when the source reports it
+    pub utc_offset_secs: Option<i32>, // offset of the source's local time from UTC, when its timestamps have one
+}
+
+/// What someone was doing at a point, as reported by the source of the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Activity {
+    Stationary,
+    Walking,
+    Running,
+    Cycling,
+    Driving,
+    Transit,
+    Flying,
+}
+
+impl Activity {
+    /// Map the activity names used by Google (IN_PASSENGER_VEHICLE, "in passenger vehicle") and sports devices (Biking)
+    pub fn from_description(description: &str) -> Option<Activity> {
+        match description.trim().to_ascii_uppercase().replace(' ', "_").as_str() {
+            "STILL" | "STATIONARY" => Some(Activity::Stationary),
+            "WALKING" | "ON_FOOT" | "HIKING" => Some(Activity::Walking),
+            "RUNNING" => Some(Activity::Running),
+            "CYCLING" | "ON_BICYCLE" | "BIKING" => Some(Activity::Cycling),
+            "IN_PASSENGER_VEHICLE" | "IN_VEHICLE" | "IN_ROAD_VEHICLE" | "IN_CAR" | "IN_TAXI" | "DRIVING" | "MOTORCYCLING" => Some(Activity::Driving),
+            "IN_BUS" | "IN_TRAIN" | "IN_SUBWAY" | "IN_TRAM" | "IN_FERRY" | "IN_RAIL_VEHICLE" | "IN_CABLECAR" | "IN_FUNICULAR" | "IN_GONDOLA_LIFT" | "SAILING" => Some(Activity::Transit),
+            "FLYING" | "IN_PLANE" => Some(Activity::Flying),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Activity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Activity::Stationary => "Stationary",
+            Activity::Walking => "Walking",
+            Activity::Running => "Running",
+            Activity::Cycling => "Cycling",
+            Activity::Driving => "Driving",
+            Activity::Transit => "Transit",
+            Activity::Flying => "Flying",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Great circle distance in kilometers between two coordinates
+pub fn haversine_distance(latitude1: f64, longitude1: f64, latitude2: f64, longitude2: f64) -> f64 {
+    let lat1_rad = latitude1.to_radians();
+    let lat2_rad = latitude2.to_radians();
+    let delta_lat = (latitude1 - latitude2).to_radians();
+    let delta_lon = (longitude1 - longitude2).to_radians();
+
+    let a = (delta_lat / 2.0).sin().powi(2) + lat1_rad.cos() * lat2_rad.cos() * (delta_lon / 2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+    SpaceTimePoint::EARTH_RADIUS * c
+}
+
+impl SpaceTimePoint {
+    const EARTH_RADIUS: f64 = 6371.0; // in kilometers
+
+    pub fn euclidean_distance(&self, latitude: f64, longitude: f64) -> f64 {
+        let delta_lat = self.latitude - latitude;
+        let delta_lon = self.longitude - longitude;
+        let avg_lat = (self.latitude + latitude) / 2.0;
+        110.25 * (delta_lat.powi(2) + (delta_lon * avg_lat.cos()).powi(2)).sqrt()
+    }
+
+    // this is a more efficient, simplified version of the haversine formula, but it overshoots
+    pub fn equirectangular_distance(&self, latitude: f64, longitude: f64) -> f64 {
+        let delta_lat = (self.latitude - latitude).to_radians();
+        let delta_lon = (self.longitude - longitude).to_radians();
+        let avg_lat = ((self.latitude + latitude) / 2.0).to_radians();
+
+        Self::EARTH_RADIUS * (delta_lat.powi(2) + (avg_lat.cos() * delta_lon).powi(2)).sqrt()
+    }
+
+    /// The offset of local time from UTC at the point, from the source or else from where it is
+    pub fn utc_offset(&self) -> FixedOffset {
+        match self.utc_offset_secs.and_then(FixedOffset::east_opt) {
+            Some(offset) => offset,
+            None => utc_offset_at(self.latitude, self.longitude, self.start_time),
+        }
+    }
+
+    pub fn haversine_distance(&self, latitude: f64, longitude: f64) -> f64 {
+        haversine_distance(self.latitude, self.longitude, latitude, longitude)
+    }
+
+    pub fn temporal_distance(&self, start_time: f64, end_time: f64) -> f64 {
+        if self.temporal_overlap(start_time, end_time) {
+            return 0.0
+        }
+        start_time.max(self.start_time.timestamp() as f64) - end_time.min(self.end_time.timestamp() as f64)
+    }
+
+    fn temporal_overlap(&self, start_time: f64, end_time: f64) -> bool {
+        let self_start = self.start_time.timestamp() as f64;
+        let  self_end = self.end_time.timestamp() as f64;
+
+        // We have an overlap if the start time of one interval is within the other interval
+        (start_time > self_start && start_time < self_end) || (self_start > start_time && self_start < end_time)        
+    }
+}
+
+/// A point between two unix timestamps with nothing else known about it, for tests
+#[cfg(test)]
+pub(crate) fn test_point(start: i64, end: i64, latitude: f64, longitude: f64) -> SpaceTimePoint {
+    SpaceTimePoint {
+        start_time: DateTime::from_timestamp(start, 0).unwrap(),
+        end_time: DateTime::from_timestamp(end, 0).unwrap(),
+        latitude,
+        longitude,
+        accuracy: None,
+        activity: None,
+        utc_offset_secs: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const ERROR: f64 = 0.001;
+    const TIME0: DateTime<Utc> = DateTime::from_timestamp_nanos(0);
+
+    #[test]
+    fn test_euclidean_distance() {
+        let point = SpaceTimePoint {
+            start_time: TIME0,
+            end_time: TIME0,
+            latitude: 41.507483,
+            longitude: -99.436554,
+            accuracy: None,
+            activity: None,
+            utc_offset_secs: None,
+        };
+
+        let distance = point.euclidean_distance(38.504048, -98.315949);
+        // Distance is undershot, but this is expected for euclidean distance calculations
+        assert!((distance - 341.355).abs() < ERROR, "Distance was actually {}", distance);
+    }
+
+    #[test]
+    fn test_haversine_distance() {
+        let point = SpaceTimePoint {
+            start_time: TIME0,
+            end_time: TIME0,
+            latitude: 41.507483,
+            longitude: -99.436554,
+            accuracy: None,
+            activity: None,
+            utc_offset_secs: None,
+        };
+
+        let distance = point.haversine_distance(38.504048, -98.315949);
+        assert!((distance - 347.328).abs() < ERROR, "Distance was actually {}", distance);
+    }
+
+    #[test]
+    fn test_utc_offset() {
+        let point = SpaceTimePoint {
+            start_time: TIME0,
+            end_time: TIME0,
+            latitude: 40.7,
+            longitude: -74.0,
+            accuracy: None,
+            activity: None,
+            utc_offset_secs: Some(-4 * 3600),
+        };
+        assert_eq!(point.utc_offset().local_minus_utc(), -4 * 3600);
+        // New York in January without an offset from the source
+        assert_eq!(SpaceTimePoint { utc_offset_secs: None, ..point }.utc_offset().local_minus_utc(), -5 * 3600);
+    }
+
+    #[test]
+    fn test_temporal_distance_overlap() {
+        let point = test_point(100, 1000, 0.0, 0.0);
+
+        assert_eq!(point.temporal_distance(500.0, 600.0), 0.0);
+        assert_eq!(point.temporal_distance(500.0, 1500.0), 0.0);
+        assert_eq!(point.temporal_distance(TIME0.timestamp() as f64, 500.0), 0.0);
+    }
+
+    #[test]
+    fn test_activity_from_description() {
+        assert_eq!(Activity::from_description("IN_PASSENGER_VEHICLE"), Some(Activity::Driving));
+        assert_eq!(Activity::from_description("in passenger vehicle"), Some(Activity::Driving));
+        assert_eq!(Activity::from_description("Biking"), Some(Activity::Cycling));
+        assert_eq!(Activity::from_description("UNKNOWN_ACTIVITY_TYPE"), None);
+    }
+
+    #[test]
+    fn test_points_between() {
+        let point = |start: i64, end: i64| test_point(start, end, 0.0, 0.0);
+        let record = SpaceTimeRecord::new(vec![point(0, 100), point(100, 200), point(200, 300), point(300, 400)], Vec::new());
+        let time = |timestamp: i64| DateTime::from_timestamp(timestamp, 0).unwrap();
+
+        assert_eq!(record.points_between(time(150), time(250)).len(), 2);
+        assert_eq!(record.points_between(time(100), time(100)).len(), 2);
+        assert_eq!(record.points_between(time(500), time(600)).len(), 0);
+        assert_eq!(record.points_between(time(-100), time(1000)).len(), 4);
+    }
+
+    #[test]
+    fn test_temporal_distance() {
+        let point = SpaceTimePoint {
+            start_time: DateTime::from_timestamp(100, 0).unwrap(),
+            end_time: DateTime::from_timestamp(1000, 0).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            accuracy: None,
+            activity: None,
+            utc_offset_secs: None,
+        };
+
+        assert_eq!(point.temporal_distance(1500.0, 2000.0), 500.0);
+        assert_eq!(point.temporal_distance(TIME0.timestamp() as f64, 50.0), 50.0);
+    } 
+}
\ No newline at end of file
@@ -0,0 +1,250 @@
+//! Finding the places someone spends most of their time, like their home and work, from where they stay
+
+use std::collections::HashMap;
+use chrono::{Datelike, Duration, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+use crate::{compute::EncounterOptions, model::{haversine_distance, SpaceTimePoint, SpaceTimeRecord}, timeline::{segment_kind, SegmentKind}};
+
+/// Stays are binned into cells about this wide before the cells are merged into places
+const GRID_CELL_M: f64 = 100.0;
+/// Cells whose centres are this close end up in the same place
+pub const PLACE_RADIUS_M: f64 = 200.0;
+/// Less time than this spent somewhere in total isn't significant
+const MIN_PLACE_DWELL_HOURS: i64 = 3;
+/// A place is home or work when at least this much of the time spent there is at night or working hours
+const MIN_LABEL_FRACTION: f64 = 0.5;
+const KM_PER_DEGREE: f64 = 111.32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaceKind {
+    Home,
+    Work,
+    Other,
+}
+
+impl std::fmt::Display for PlaceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PlaceKind::Home => write!(f, "Home"),
+            PlaceKind::Work => write!(f, "Work"),
+            PlaceKind::Other => write!(f, "Frequent place"),
+        }
+    }
+}
+
+/// Somewhere a person stays often, at the dwell weighted centre of their stays
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Place {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub dwell_secs: i64,
+    /// How many separate stays there were
+    pub visits: usize,
+    pub kind: PlaceKind,
+}
+
+impl Place {
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        haversine_distance(self.latitude, self.longitude, latitude, longitude) * 1000.0 <= PLACE_RADIUS_M
+    }
+}
+
+/// The place a location is at, if any
+pub fn place_at(places: &[Place], latitude: f64, longitude: f64) -> Option<&Place> {
+    places.iter().find(|place| place.contains(latitude, longitude))
+}
+
+/// Running totals for a cell or place, positions summed weighted by dwell so the centre leans towards long stays
+#[derive(Default)]
+struct Dwell {
+    weighted_latitude: f64,
+    weighted_longitude: f64,
+    secs: i64,
+    night_secs: i64,
+    working_secs: i64,
+    visits: usize,
+}
+
+impl Dwell {
+    fn centre(&self) -> (f64, f64) {
+        let weight = self.secs.max(1) as f64;
+        (self.weighted_latitude / weight, self.weighted_longitude / weight)
+    }
+
+    fn add(&mut self, other: &Dwell) {
+        self.weighted_latitude += other.weighted_latitude;
+        self.weighted_longitude += other.weighted_longitude;
+        self.secs += other.secs;
+        self.night_secs += other.night_secs;
+        self.working_secs += other.working_secs;
+        self.visits += other.visits;
+    }
+}
+
+/// Split a stay by the hour to count how much of it falls at night (22:00-06:00) and in weekday working hours (09:00-17:00),
+/// in the local time where it was
+fn dwell(point: &SpaceTimePoint, secs: i64) -> Dwell {
+    let offset = Duration::seconds(point.utc_offset().local_minus_utc() as i64);
+    let (mut night_secs, mut working_secs) = (0, 0);
+    let mut time = point.start_time + offset;
+    let end_time = time + Duration::seconds(secs);
+    while time < end_time {
+        let next_hour = (time + Duration::hours(1)).with_minute(0).and_then(|time| time.with_second(0)).unwrap_or(end_time).min(end_time);
+        let hour_secs = (next_hour - time).num_seconds().max(1);
+        match (time.hour(), time.weekday()) {
+            (22..=23 | 0..=5, _) => night_secs += hour_secs,
+            (9..=16, Weekday::Sat | Weekday::Sun) => (),
+            (9..=16, _) => working_secs += hour_secs,
+            _ => (),
+        }
+        time += Duration::seconds(hour_secs);
+    }
+    Dwell {
+        weighted_latitude: point.latitude * secs as f64,
+        weighted_longitude: point.longitude * secs as f64,
+        secs,
+        night_secs,
+        working_secs,
+        visits: 1,
+    }
+}
+
+/// The places someone stays the longest in total, longest first, with the one they mostly spend nights at
+/// labelled home and the one they mostly spend weekday working hours at labelled work
+pub fn frequent_places(record: &SpaceTimeRecord, max_places: usize) -> Vec<Place> {
+    let mut cells: HashMap<(i64, i64), Dwell> = HashMap::new();
+    let mut previous_cell = None;
+    // A gap in the history isn't time spent at the last place, so a raw fix lasts at most as long as matching allows
+    let max_fix_secs = EncounterOptions::default().max_time_gap_secs;
+    for (i, point) in record.points.iter().enumerate() {
+        if segment_kind(point, record.points.get(i + 1)) != SegmentKind::Visit {
+            previous_cell = None;
+            continue;
+        }
+        // A raw fix only says where someone was at one moment, they stayed there until the next one
+        let end_time = match (point.end_time > point.start_time, record.points.get(i + 1)) {
+            (false, Some(next_point)) => next_point.start_time.min(point.start_time + Duration::seconds(max_fix_secs)),
+            _ => point.end_time,
+        };
+        let secs = (end_time - point.start_time).num_seconds();
+        let cell_size = GRID_CELL_M / 1000.0 / KM_PER_DEGREE;
+        let cell = ((point.latitude / cell_size).floor() as i64, (point.longitude * point.latitude.to_radians().cos() / cell_size).floor() as i64);
+        let mut point_dwell = dwell(point, secs);
+        // Consecutive points in the same cell are one visit
+        if previous_cell == Some(cell) {
+            point_dwell.visits = 0;
+        }
+        previous_cell = Some(cell);
+        cells.entry(cell).or_default().add(&point_dwell);
+    }
+
+    // Merge cells into places starting from the busiest, so a place isn't split along a cell boundary.
+    // Ties are broken by cell so the places don't depend on the map's order
+    let mut cells: Vec<((i64, i64), Dwell)> = cells.into_iter().collect();
+    cells.sort_by_key(|(cell, dwell)| (std::cmp::Reverse(dwell.secs), *cell));
+    let mut places: Vec<Dwell> = Vec::new();
+    for (_, cell_dwell) in cells {
+        let (latitude, longitude) = cell_dwell.centre();
+        let nearby = places.iter_mut().find(|place| {
+            let (place_latitude, place_longitude) = place.centre();
+            haversine_distance(place_latitude, place_longitude, latitude, longitude) * 1000.0 <= PLACE_RADIUS_M
+        });
+        match nearby {
+            Some(place) => place.add(&cell_dwell),
+            None => places.push(cell_dwell),
+        }
+    }
+    places.retain(|place| place.secs >= MIN_PLACE_DWELL_HOURS * 3600);
+    places.sort_by_key(|place| std::cmp::Reverse(place.secs));
+
+    let labelled = |fraction: fn(&Dwell) -> i64, excluded: Option<usize>| places.iter().enumerate()
+        .filter(|(index, place)| Some(*index) != excluded && fraction(place) as f64 >= place.secs as f64 * MIN_LABEL_FRACTION)
+        .max_by_key(|(_, place)| fraction(place))
+        .map(|(index, _)| index);
+    let home = labelled(|place| place.night_secs, None);
+    let work = labelled(|place| place.working_secs, home);
+
+    places.iter().enumerate()
+        .take(max_places)
+        .map(|(index, place)| {
+            let (latitude, longitude) = place.centre();
+            Place {
+                latitude,
+                longitude,
+                dwell_secs: place.secs,
+                visits: place.visits,
+                kind: match Some(index) {
+                    index if index == home => PlaceKind::Home,
+                    index if index == work => PlaceKind::Work,
+                    _ => PlaceKind::Other,
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{test_point, Activity};
+
+    fn stay(start: i64, hours: i64, latitude: f64) -> SpaceTimePoint {
+        SpaceTimePoint { activity: Some(Activity::Stationary), ..test_point(start, start + hours * 3600, latitude, 0.0) }
+    }
+
+    #[test]
+    fn test_frequent_places() {
+        // 2024-03-04 was a Monday
+        let monday = 1709510400;
+        let mut points = Vec::new();
+        for day in 0..5 {
+            let midnight = monday + day * 86400;
+            points.push(stay(midnight, 8, 51.5));
+            points.push(stay(midnight + 9 * 3600, 8, 51.52));
+            points.push(stay(midnight + 18 * 3600, 1, 51.6 + day as f64 * 0.1));
+            points.push(stay(midnight + 20 * 3600, 4, 51.5));
+        }
+        let record = SpaceTimeRecord::new(points, Vec::new());
+
+        let places = frequent_places(&record, 5);
+        // The evening stays are all somewhere different so none of them add up to a place
+        assert_eq!(places.len(), 2);
+        assert_eq!(places[0].kind, PlaceKind::Home);
+        assert_eq!(places[0].dwell_secs, 5 * 12 * 3600);
+        // Each night at home runs on from the evening before
+        assert_eq!(places[0].visits, 6);
+        assert!((places[0].latitude - 51.5).abs() < 0.001);
+        assert_eq!(places[1].kind, PlaceKind::Work);
+        assert_eq!(places[1].visits, 5);
+
+        assert_eq!(place_at(&places, 51.5201, 0.0).map(|place| place.kind), Some(PlaceKind::Work));
+        assert!(place_at(&places, 51.51, 0.0).is_none());
+        assert_eq!(frequent_places(&record, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_frequent_places_gap() {
+        // Fixes every 5 minutes for 4 hours, then nothing for a week
+        let fix = |start: i64, latitude: f64| SpaceTimePoint { activity: Some(Activity::Stationary), ..test_point(start, start, latitude, 0.0) };
+        let mut points: Vec<SpaceTimePoint> = (0..48).map(|i| fix(i * 300, 51.5)).collect();
+        points.push(fix(7 * 86400, 52.0));
+        let record = SpaceTimeRecord::new(points, Vec::new());
+
+        let places = frequent_places(&record, 5);
+        assert_eq!(places.len(), 1);
+        assert_eq!(places[0].dwell_secs, 48 * 300);
+    }
+
+    #[test]
+    fn test_dwell() {
+        // Saturday 2024-03-09 from 20:30 to 23:30
+        let point = stay(1710016200, 3, 0.0);
+        let saturday = dwell(&point, 3 * 3600);
+        assert_eq!(saturday.night_secs, 3600 + 1800);
+        assert_eq!(saturday.working_secs, 0);
+
+        // Monday 2024-03-04 from 08:00 to 10:00, in working hours from 09:00
+        let point = stay(1709539200, 2, 0.0);
+        assert_eq!(dwell(&point, 2 * 3600).working_secs, 3600);
+    }
+}
@@ -0,0 +1,218 @@
+//! Removing and blurring the sensitive parts of a record before it's shared
+
+use std::{fmt, str::FromStr};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{exclusion::ExclusionZone, model::{SpaceTimePoint, SpaceTimeRecord}, tokens::Cell};
+
+/// Whole days to leave out, both dates included and in the local time of each point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DateRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl DateRange {
+    pub fn overlaps(&self, point: &SpaceTimePoint) -> bool {
+        let offset = point.utc_offset();
+        point.start_time.with_timezone(&offset).date_naive() <= self.to && point.end_time.with_timezone(&offset).date_naive() >= self.from
+    }
+}
+
+/// Parses "YYYY-MM-DD" for a single day or "YYYY-MM-DD..YYYY-MM-DD"
+impl FromStr for DateRange {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let parse_date = |text: &str| text.trim().parse::<NaiveDate>().map_err(|err| format!("Invalid date {:?}: {}", text.trim(), err));
+        let (from, to) = match range.split_once("..") {
+            Some((from, to)) => (parse_date(from)?, parse_date(to)?),
+            None => (parse_date(range)?, parse_date(range)?),
+        };
+        match from <= to {
+            true => Ok(DateRange { from, to }),
+            false => Err("The range ends before it starts".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.from, self.to)
+    }
+}
+
+/// What to remove or blur, the default changes nothing
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RedactionOptions {
+    /// Points inside any of these are dropped
+    pub zones: Vec<ExclusionZone>,
+    /// Points on any of these days are dropped
+    pub dates: Vec<DateRange>,
+    /// Coordinates are moved to the centre of grid cells this size, in meters
+    pub grid_m: Option<f64>,
+    /// Times are widened out to whole buckets this long, in minutes
+    pub time_bucket_mins: Option<i64>,
+}
+
+/// How much of a record was removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionReport {
+    pub points_before: usize,
+    pub points_after: usize,
+    pub dropped_in_zones: usize,
+    pub dropped_in_dates: usize,
+    /// Points that became the same as the one before after snapping and coarsening, and were merged into it
+    pub merged: usize,
+    /// Points somewhere else that coarsening put inside the time of the one before, dropped as no one is in two
+    /// places at once
+    pub dropped_in_buckets: usize,
+    /// Points moved to a grid cell centre
+    pub snapped: usize,
+}
+
+impl RedactionReport {
+    pub fn removed(&self) -> usize {
+        self.points_before - self.points_after
+    }
+}
+
+impl fmt::Display for RedactionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} points removed ({} in excluded zones, {} on excluded dates, {} merged, {} inside an earlier time bucket), {} snapped to the grid",
+            self.removed(), self.points_before, self.dropped_in_zones, self.dropped_in_dates, self.merged, self.dropped_in_buckets, self.snapped)
+    }
+}
+
+/// A copy of the record with the options applied, and what was removed.
+///
+/// Dates and zones are dropped first, so points are checked against where and when they really were. Snapping and
+/// coarsening can then leave neighbouring points overlapping or identical, those are trimmed or merged to keep the
+/// record sorted, and points somewhere else that end up entirely inside the time of the point before are dropped. Warnings are left out as they can quote the original file
+pub fn redact(record: &SpaceTimeRecord, options: &RedactionOptions) -> (SpaceTimeRecord, RedactionReport) {
+    let mut report = RedactionReport { points_before: record.points.len(), ..RedactionReport::default() };
+    let bucket_secs = options.time_bucket_mins.filter(|minutes| *minutes > 0).map(|minutes| minutes * 60);
+    let grid_m = options.grid_m.filter(|grid_m| *grid_m > 0.0);
+
+    let mut points: Vec<SpaceTimePoint> = Vec::with_capacity(record.points.len());
+    for point in &record.points {
+        if options.dates.iter().any(|range| range.overlaps(point)) {
+            report.dropped_in_dates += 1;
+            continue;
+        }
+        if options.zones.iter().any(|zone| zone.contains(point)) {
+            report.dropped_in_zones += 1;
+            continue;
+        }
+
+        let mut point = point.clone();
+        if let Some(grid_m) = grid_m {
+            (point.latitude, point.longitude) = Cell::at(point.latitude, point.longitude, grid_m).centre(grid_m);
+            // A snapped point can be up to half the cell's diagonal from where it was
+            point.accuracy = Some(point.accuracy.unwrap_or_default().max(grid_m * std::f64::consts::FRAC_1_SQRT_2));
+            report.snapped += 1;
+        }
+        if let Some(bucket_secs) = bucket_secs {
+            point.start_time = to_bucket(point.start_time, bucket_secs, false);
+            point.end_time = to_bucket(point.end_time, bucket_secs, true);
+        }
+
+        if let Some(previous) = points.last_mut() {
+            let same_place = previous.latitude == point.latitude && previous.longitude == point.longitude && previous.activity == point.activity;
+            if same_place && point.start_time <= previous.end_time {
+                previous.end_time = previous.end_time.max(point.end_time);
+                report.merged += 1;
+                continue;
+            }
+            if point.end_time <= previous.end_time {
+                report.dropped_in_buckets += 1;
+                continue;
+            }
+            point.start_time = point.start_time.max(previous.end_time);
+        }
+        points.push(point);
+    }
+
+    report.points_after = points.len();
+    (SpaceTimeRecord::new(points, Vec::new()), report)
+}
+
+/// The start of the bucket the time is in, or the end when rounding up
+fn to_bucket(time: DateTime<Utc>, bucket_secs: i64, round_up: bool) -> DateTime<Utc> {
+    let seconds = time.timestamp();
+    let mut bucket = seconds.div_euclid(bucket_secs) * bucket_secs;
+    if round_up && (bucket < seconds || time.timestamp_subsec_nanos() > 0) {
+        bucket += bucket_secs;
+    }
+    DateTime::from_timestamp(bucket, 0).unwrap_or(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_point;
+
+    /// In UTC, so dates are the same as in the timestamps
+    fn point(start: i64, end: i64, latitude: f64, longitude: f64) -> SpaceTimePoint {
+        SpaceTimePoint { utc_offset_secs: Some(0), ..test_point(start, end, latitude, longitude) }
+    }
+
+    #[test]
+    fn test_date_range_from_str() {
+        let range: DateRange = "2024-03-01..2024-03-05".parse().unwrap();
+        assert_eq!(range.to_string(), "2024-03-01..2024-03-05");
+        let day: DateRange = "2024-03-01".parse().unwrap();
+        assert_eq!(day.from, day.to);
+        assert!("2024-03-05..2024-03-01".parse::<DateRange>().is_err());
+        assert!("2024-13-01".parse::<DateRange>().is_err());
+    }
+
+    #[test]
+    fn test_redact_zones_and_dates() {
+        // 2024-03-01 00:00 UTC
+        let day = 1709251200;
+        let record = SpaceTimeRecord::new(vec![
+            point(day, day + 600, 40.0, -75.0),
+            point(day + 600, day + 1200, 40.1, -75.0),
+            point(day + 86400, day + 87000, 40.1, -75.0),
+        ], Vec::new());
+        let options = RedactionOptions {
+            zones: vec!["40.0,-75.0,100".parse().unwrap()],
+            dates: vec!["2024-03-02".parse().unwrap()],
+            ..RedactionOptions::default()
+        };
+        let (redacted, report) = redact(&record, &options);
+        assert_eq!(redacted.points.len(), 1);
+        assert_eq!(redacted.points[0].latitude, 40.1);
+        assert_eq!((report.dropped_in_zones, report.dropped_in_dates, report.removed()), (1, 1, 2));
+
+        let (unchanged, report) = redact(&record, &RedactionOptions::default());
+        assert_eq!(unchanged.points.len(), 3);
+        assert_eq!(unchanged.points[2].latitude, 40.1);
+        assert_eq!(report.removed(), 0);
+    }
+
+    #[test]
+    fn test_redact_grid_and_buckets() {
+        let record = SpaceTimeRecord::new(vec![
+            point(60, 120, 40.0001, -75.0001),
+            point(120, 300, 40.0002, -75.0002),
+            point(1000, 1100, 40.05, -75.0),
+            point(1100, 1150, 40.06, -75.0),
+        ], Vec::new());
+        let options = RedactionOptions { grid_m: Some(500.0), time_bucket_mins: Some(15), ..RedactionOptions::default() };
+        let (redacted, report) = redact(&record, &options);
+
+        // The first two land in the same cell and bucket, the last is in another cell but covered by the bucket before it
+        assert_eq!(redacted.points.len(), 2);
+        assert_eq!((report.merged, report.dropped_in_buckets, report.snapped), (1, 1, 4));
+        assert_eq!(report.removed(), 2);
+        assert_eq!(redacted.points[0].start_time.timestamp(), 0);
+        assert_eq!(redacted.points[0].end_time.timestamp(), 900);
+        assert_eq!(redacted.points[1].start_time.timestamp(), 900);
+        assert_eq!(redacted.points[1].end_time.timestamp(), 1800);
+        assert!(redacted.points[0].accuracy.unwrap() > 350.0);
+        assert!(redacted.points[0].haversine_distance(40.0001, -75.0001) * 1000.0 < 500.0);
+        assert!(redacted.points.windows(2).all(|w| w[0].end_time <= w[1].start_time));
+    }
+}
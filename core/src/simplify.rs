@@ -0,0 +1,213 @@
+//! Shrinking dense records before matching, by collapsing stays into single points and thinning out movement
+//!
+//! Matching compares points rather than the lines between them, and a point stands for where someone was over its
+//! whole interval. So every point that's dropped has its time taken over by a kept point within the tolerance of it,
+//! and two people's distance in the simplified records is never more than twice the tolerance from what it was
+
+use serde::{Deserialize, Serialize};
+use crate::model::{Activity, SpaceTimePoint, SpaceTimeRecord};
+
+/// How much a record can be changed by simplifying it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SimplifyOptions {
+    /// Furthest a dropped point can be from the point that takes over its time, in meters
+    pub tolerance_m: f64,
+    /// Shortest time spent within the tolerance of one place for it to count as a stay
+    pub min_stay_secs: i64,
+    /// Points further apart in time than this are never merged, so simplifying doesn't fill gaps in the history
+    pub max_gap_secs: i64,
+}
+
+impl Default for SimplifyOptions {
+    /// A fifth of the default encounter distance, and the default time gap
+    fn default() -> Self {
+        SimplifyOptions { tolerance_m: 20.0, min_stay_secs: 5 * 60, max_gap_secs: 5 * 60 }
+    }
+}
+
+/// How much smaller a record got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimplifyReport {
+    pub points_before: usize,
+    pub points_after: usize,
+    /// Stays collapsed into a single point
+    pub stays: usize,
+}
+
+/// A copy of the record with stays collapsed and movement simplified. Stays are runs of points within the tolerance
+/// of their first point lasting at least `min_stay_secs`, they become one stationary point at the first point lasting
+/// the whole stay. What's left between stays is simplified with `douglas_peucker`
+pub fn simplify(record: &SpaceTimeRecord, options: &SimplifyOptions) -> (SpaceTimeRecord, SimplifyReport) {
+    let points = &record.points;
+    let mut simplified = Vec::with_capacity(points.len());
+    let mut stays = 0;
+    let (mut movement_start, mut i) = (0, 0);
+    while i < points.len() {
+        let stay_length = 1 + points.windows(2).skip(i)
+            .take_while(|pair| continues(&pair[0], &pair[1], options) && within_tolerance(&points[i], &pair[1], options))
+            .count();
+        let stay_end = i + stay_length;
+        if stay_length > 1 && (points[stay_end - 1].end_time - points[i].start_time).num_seconds() >= options.min_stay_secs {
+            simplified.extend(douglas_peucker(&points[movement_start..i], options));
+            simplified.push(SpaceTimePoint {
+                end_time: points[stay_end - 1].end_time,
+                activity: Some(Activity::Stationary),
+                ..points[i].clone()
+            });
+            stays += 1;
+            (movement_start, i) = (stay_end, stay_end);
+        } else {
+            i += 1;
+        }
+    }
+    simplified.extend(douglas_peucker(&points[movement_start..], options));
+
+    let report = SimplifyReport { points_before: points.len(), points_after: simplified.len(), stays };
+    (SpaceTimeRecord::new(simplified, record.warnings.clone()), report)
+}
+
+/// Douglas-Peucker, splitting at the point furthest from the kept point that would take over its time until every
+/// point is within the tolerance. Between two kept points, the points before the middle of the time between them
+/// go to the earlier one and the rest to the later one. Runs of points are never merged across a gap
+pub fn douglas_peucker(points: &[SpaceTimePoint], options: &SimplifyOptions) -> Vec<SpaceTimePoint> {
+    let mut simplified = Vec::new();
+    let mut run_start = 0;
+    for end in 1..=points.len() {
+        if end == points.len() || !continues(&points[end - 1], &points[end], options) {
+            simplify_run(&points[run_start..end], options, &mut simplified);
+            run_start = end;
+        }
+    }
+    simplified
+}
+
+fn simplify_run(points: &[SpaceTimePoint], options: &SimplifyOptions, simplified: &mut Vec<SpaceTimePoint>) {
+    if points.len() <= 2 {
+        simplified.extend_from_slice(points);
+        return;
+    }
+    let mut kept = vec![false; points.len()];
+    (kept[0], kept[points.len() - 1]) = (true, true);
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let furthest = (first + 1..last)
+            .map(|index| {
+                let kept_point = &points[covering(points, first, last, index)];
+                (index, points[index].haversine_distance(kept_point.latitude, kept_point.longitude) * 1000.0)
+            })
+            .max_by(|(_, distance1), (_, distance2)| distance1.total_cmp(distance2));
+        if let Some((index, _)) = furthest.filter(|(_, distance)| *distance > options.tolerance_m) {
+            kept[index] = true;
+            ranges.push((first, index));
+            ranges.push((index, last));
+        }
+    }
+
+    // Each kept point is widened to cover the dropped points that went to it
+    let kept: Vec<usize> = (0..points.len()).filter(|index| kept[*index]).collect();
+    let mut start_index = 0;
+    for (position, &index) in kept.iter().enumerate() {
+        let end_index = match kept.get(position + 1) {
+            Some(&next) => (index..next).rev().find(|dropped| covering(points, index, next, *dropped) == index).unwrap_or(index),
+            None => index,
+        };
+        simplified.push(SpaceTimePoint {
+            start_time: points[start_index].start_time,
+            end_time: points[end_index].end_time,
+            ..points[index].clone()
+        });
+        start_index = end_index + 1;
+    }
+}
+
+/// Which of two kept points takes over a dropped point between them, the one nearer in time
+fn covering(points: &[SpaceTimePoint], first: usize, last: usize, index: usize) -> usize {
+    match points[index].start_time - points[first].start_time <= points[last].start_time - points[index].start_time {
+        true => first,
+        false => last,
+    }
+}
+
+fn continues(previous: &SpaceTimePoint, next: &SpaceTimePoint, options: &SimplifyOptions) -> bool {
+    (next.start_time - previous.end_time).num_seconds() <= options.max_gap_secs
+}
+
+fn within_tolerance(anchor: &SpaceTimePoint, point: &SpaceTimePoint, options: &SimplifyOptions) -> bool {
+    anchor.haversine_distance(point.latitude, point.longitude) * 1000.0 <= options.tolerance_m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_point;
+    use crate::compute::{analyze, EncounterOptions};
+
+    /// Points every 10 seconds, each lasting until the next
+    fn samples(start: i64, positions: &[(f64, f64)]) -> Vec<SpaceTimePoint> {
+        positions.iter().enumerate()
+            .map(|(i, (latitude, longitude))| test_point(start + i as i64 * 10, start + i as i64 * 10 + 10, *latitude, *longitude))
+            .collect()
+    }
+
+    #[test]
+    fn test_simplify_stay() {
+        // Ten minutes of jitter at home, then walking away about 11m every sample
+        let mut positions: Vec<(f64, f64)> = (0..60).map(|i| (40.0 + (i % 3) as f64 * 0.00003, -75.0)).collect();
+        positions.extend((3..32).map(|i| (40.0 + i as f64 * 0.0001, -75.0)));
+        let record = SpaceTimeRecord::new(samples(0, &positions), Vec::new());
+        let (simplified, report) = simplify(&record, &SimplifyOptions::default());
+
+        assert_eq!(report.stays, 1);
+        assert_eq!(report.points_before, 89);
+        assert!(report.points_after < 20, "{}", report.points_after);
+        let stay = &simplified.points[0];
+        assert_eq!(stay.activity, Some(Activity::Stationary));
+        assert_eq!((stay.start_time.timestamp(), stay.end_time.timestamp()), (0, 600));
+        assert_eq!(simplified.points.last().unwrap().end_time, record.points.last().unwrap().end_time);
+    }
+
+    #[test]
+    fn test_douglas_peucker_keeps_points_within_tolerance() {
+        let options = SimplifyOptions::default();
+        let record = SpaceTimeRecord::new(samples(0, &(0..200).map(|i| (40.0 + i as f64 * 0.00005, -75.0 + (i as f64 / 20.0).sin() * 0.0005)).collect::<Vec<_>>()), Vec::new());
+        let simplified = douglas_peucker(&record.points, &options);
+        assert!(simplified.len() < record.points.len() / 2, "{}", simplified.len());
+        assert!(simplified.windows(2).all(|w| w[0].end_time == w[1].start_time));
+
+        // Every original point is within the tolerance of the simplified point covering its time
+        for original in &record.points {
+            let covering = simplified.iter().find(|point| point.start_time <= original.start_time && original.start_time < point.end_time).unwrap();
+            assert!(covering.haversine_distance(original.latitude, original.longitude) * 1000.0 <= options.tolerance_m);
+        }
+    }
+
+    #[test]
+    fn test_douglas_peucker_keeps_gaps() {
+        let points = vec![test_point(0, 10, 40.0, -75.0), test_point(10, 20, 40.00001, -75.0), test_point(20, 30, 40.00002, -75.0), test_point(3600, 3610, 40.00002, -75.0)];
+        let simplified = douglas_peucker(&points, &SimplifyOptions::default());
+        assert_eq!(simplified.len(), 3);
+        assert_eq!((simplified[0].start_time.timestamp(), simplified[0].end_time.timestamp()), (0, 20));
+        assert_eq!((simplified[1].start_time.timestamp(), simplified[1].end_time.timestamp()), (20, 30));
+        assert_eq!(simplified[2].start_time.timestamp(), 3600);
+    }
+
+    #[test]
+    fn test_simplify_keeps_encounters() {
+        // Two people walk the same street a minute apart, then one waits at the corner
+        let walk: Vec<(f64, f64)> = (0..60).map(|i| (40.0 + i as f64 * 0.0001, -75.0)).collect();
+        let mut waiting = walk.clone();
+        waiting.extend([(40.006, -75.0); 60]);
+        let record1 = SpaceTimeRecord::new(samples(0, &walk), Vec::new());
+        let record2 = SpaceTimeRecord::new(samples(60, &waiting), Vec::new());
+        let options = EncounterOptions::default();
+
+        let encounters = analyze(&record1, &record2, &options);
+        let simplify_options = SimplifyOptions::default();
+        let (simplified1, _) = simplify(&record1, &simplify_options);
+        let (simplified2, _) = simplify(&record2, &simplify_options);
+        let simplified_encounters = analyze(&simplified1, &simplified2, &options);
+        assert!(simplified1.points.len() + simplified2.points.len() < (record1.points.len() + record2.points.len()) / 2);
+        assert_eq!(encounters.len(), simplified_encounters.len());
+        assert!((encounters[0].min_distance_km - simplified_encounters[0].min_distance_km).abs() * 1000.0 <= 2.0 * simplify_options.tolerance_m);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::model::{Activity, SpaceTimeRecord, SpaceTimePoint};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Whether someone was staying somewhere or on the move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SegmentKind {
+    Visit,
+    Movement,
+}
+
+/// A stretch of time spent visiting somewhere or moving, for drawing a history on a timeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineSegment {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub kind: SegmentKind,
+}
+
+/// Points further apart in time than this leave a gap in the timeline
+pub const DEFAULT_MAX_GAP_MINUTES: i64 = 15;
+
+/// Points without an activity that last at least this long without going anywhere are treated as visits
+const MIN_INFERRED_VISIT_MINUTES: i64 = 10;
+const MAX_INFERRED_VISIT_MOVEMENT_KM: f64 = 0.1;
+
+pub(crate) fn segment_kind(point: &SpaceTimePoint, next_point: Option<&SpaceTimePoint>) -> SegmentKind {
+    match point.activity {
+        Some(Activity::Stationary) => SegmentKind::Visit,
+        Some(_) => SegmentKind::Movement,
+        // Raw fixes don't say what the person was doing, but a long time spent in one spot is a visit
+        None => {
+            let long_enough = point.end_time - point.start_time >= Duration::minutes(MIN_INFERRED_VISIT_MINUTES);
+            let stayed = next_point.is_none_or(|next_point| point.haversine_distance(next_point.latitude, next_point.longitude) < MAX_INFERRED_VISIT_MOVEMENT_KM);
+            match long_enough && stayed {
+                true => SegmentKind::Visit,
+                false => SegmentKind::Movement,
+            }
+        }
+    }
+}
+
+/// Condense a record into visit and movement segments, merging neighbouring points of the same kind
+pub fn timeline_segments(record: &SpaceTimeRecord, max_gap: Duration) -> Vec<TimelineSegment> {
+    let mut segments: Vec<TimelineSegment> = Vec::new();
+    for (i, point) in record.points.iter().enumerate() {
+        let kind = segment_kind(point, record.points.get(i + 1));
+        match segments.last_mut() {
+            Some(segment) if segment.kind == kind && point.start_time <= segment.end_time + max_gap => {
+                segment.end_time = segment.end_time.max(point.end_time);
+            },
+            _ => segments.push(TimelineSegment { start_time: point.start_time, end_time: point.end_time, kind }),
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_point;
+
+    fn point(start: i64, end: i64, latitude: f64, activity: Option<Activity>) -> SpaceTimePoint {
+        SpaceTimePoint { activity, ..test_point(start, end, latitude, 0.0) }
+    }
+
+    #[test]
+    fn test_timeline_segments() {
+        let record = SpaceTimeRecord::new(vec![
+            point(0, 3600, 0.0, Some(Activity::Stationary)),
+            point(3600, 3700, 0.0, Some(Activity::Walking)),
+            point(3700, 3800, 0.001, Some(Activity::Walking)),
+            point(3800, 3800, 0.002, None), // a single fix while moving
+            point(7200, 7300, 0.01, None), // after a gap
+            point(7300, 10000, 0.05, None), // long stay, is a visit
+        ], Vec::new());
+
+        let segments = timeline_segments(&record, Duration::minutes(DEFAULT_MAX_GAP_MINUTES));
+        let summary: Vec<(i64, i64, SegmentKind)> = segments.iter().map(|segment| (segment.start_time.timestamp(), segment.end_time.timestamp(), segment.kind)).collect();
+        assert_eq!(summary, vec![
+            (0, 3600, SegmentKind::Visit),
+            (3600, 3800, SegmentKind::Movement),
+            (7200, 7300, SegmentKind::Movement),
+            (7300, 10000, SegmentKind::Visit),
+        ]);
+    }
+}
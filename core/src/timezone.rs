@@ -0,0 +1,84 @@
+//! Working out the local time at a location without going online, for showing encounters in the time they happened in
+
+use std::sync::LazyLock;
+use chrono::{DateTime, FixedOffset, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use tzf_rs::DefaultFinder;
+
+/// Timezone boundary polygons from timezone-boundary-builder, bundled with tzf-rs and simplified to a few MB.
+/// Loaded the first time a timezone is looked up
+static FINDER: LazyLock<DefaultFinder> = LazyLock::new(DefaultFinder::new);
+
+/// The timezone at a location, including the Etc/GMT zones out at sea
+pub fn timezone_at(latitude: f64, longitude: f64) -> Option<Tz> {
+    FINDER.get_tz_name(longitude, latitude).parse().ok()
+}
+
+/// The offset from UTC at a location and time, following daylight saving, or the nautical offset of an hour per
+/// 15 degrees of longitude where there's no timezone such as near the poles
+pub fn utc_offset_at(latitude: f64, longitude: f64, time: DateTime<Utc>) -> FixedOffset {
+    match timezone_at(latitude, longitude) {
+        Some(timezone) => timezone.offset_from_utc_datetime(&time.naive_utc()).fix(),
+        None => FixedOffset::east_opt((longitude / 15.0).round() as i32 * 3600).expect("nautical offsets are within a day"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timezone_at() {
+        assert_eq!(timezone_at(40.7, -74.0), Some(Tz::America__New_York));
+        assert_eq!(timezone_at(33.4, -112.1), Some(Tz::America__Phoenix));
+        assert_eq!(timezone_at(51.5, -0.1), Some(Tz::Europe__London));
+        assert_eq!(timezone_at(52.5, 13.4), Some(Tz::Europe__Berlin));
+        assert_eq!(timezone_at(-33.9, 151.2), Some(Tz::Australia__Sydney));
+        assert_eq!(timezone_at(0.0, -140.0), Some(Tz::Etc__GMTPlus9));
+    }
+
+    #[test]
+    fn test_timezone_at_border_cities() {
+        let cities = [
+            ((28.61, 77.21), Tz::Asia__Kolkata),
+            ((36.16, -86.78), Tz::America__Chicago), // Nashville
+            ((34.73, -86.59), Tz::America__Chicago), // Huntsville
+            ((33.52, -86.80), Tz::America__Chicago), // Birmingham, Alabama
+            ((43.62, -116.20), Tz::America__Boise),
+            ((41.80, 123.43), Tz::Asia__Shanghai), // Shenyang
+            ((43.88, 125.32), Tz::Asia__Shanghai), // Changchun
+            ((43.12, 131.89), Tz::Asia__Vladivostok),
+            ((43.24, 76.89), Tz::Asia__Almaty),
+            ((33.31, 44.36), Tz::Asia__Baghdad),
+            ((34.53, 69.17), Tz::Asia__Kabul),
+            ((16.87, 96.20), Tz::Asia__Yangon),
+            ((21.16, -86.85), Tz::America__Cancun),
+            ((37.26, -6.94), Tz::Europe__Madrid), // Huelva
+            ((38.88, -6.97), Tz::Europe__Madrid), // Badajoz
+            ((49.92, 1.08), Tz::Europe__Paris), // Dieppe
+            ((50.73, 1.61), Tz::Europe__Paris), // Boulogne
+            ((36.75, 3.06), Tz::Africa__Algiers),
+            ((36.81, 10.18), Tz::Africa__Tunis),
+            ((35.76, -5.83), Tz::Africa__Casablanca), // Tangier
+            ((54.71, 20.45), Tz::Europe__Kaliningrad),
+            ((31.76, -106.49), Tz::America__Denver), // El Paso
+        ];
+        for ((latitude, longitude), timezone) in cities {
+            assert_eq!(timezone_at(latitude, longitude), Some(timezone), "{}, {}", latitude, longitude);
+        }
+    }
+
+    #[test]
+    fn test_utc_offset_at() {
+        let winter = DateTime::from_timestamp(1_704_067_200, 0).unwrap(); // 2024-01-01
+        let summer = DateTime::from_timestamp(1_719_792_000, 0).unwrap(); // 2024-07-01
+        assert_eq!(utc_offset_at(40.7, -74.0, winter).local_minus_utc(), -5 * 3600);
+        assert_eq!(utc_offset_at(40.7, -74.0, summer).local_minus_utc(), -4 * 3600);
+        assert_eq!(utc_offset_at(33.4, -112.1, summer).local_minus_utc(), -7 * 3600);
+        assert_eq!(utc_offset_at(-33.9, 151.2, winter).local_minus_utc(), 11 * 3600);
+        // Out in the Pacific, 140 degrees west is 9 hours behind
+        assert_eq!(utc_offset_at(0.0, -140.0, winter).local_minus_utc(), -9 * 3600);
+        assert_eq!(utc_offset_at(28.61, 77.21, winter).local_minus_utc(), 5 * 3600 + 1800);
+        assert_eq!(utc_offset_at(54.71, 20.45, summer).local_minus_utc(), 2 * 3600);
+    }
+}
@@ -0,0 +1,366 @@
+//! Finding possible encounters without either person sharing their location history, with private set intersection.
+//!
+//! Each coarse grid cell and time bucket someone was in is hashed to a point in the Ristretto group and blinded by
+//! multiplying it with a key only they know. The two people swap their blinded tokens, then each blinds the other's
+//! tokens again with their own key and sends them back. Blinding commutes, so a token blinded by both keys is the
+//! same whichever key went first, and the doubly blinded tokens are equal exactly where both people were in the same
+//! cell and bucket. Each person learns which of their own cells and buckets matched and nothing else, as without the
+//! other's key there's no way to make their token for a guess of where they were.
+//!
+//! Neither side can check that the other's tokens come from a real history, so someone could make tokens for places
+//! and times they only guess the other person was in and learn whether they were, much as if they had been there.
+//! And anyone who knows the cell and bucket of one of a person's tokens, such as a match, can test guesses of the key
+//! behind it, so keys come from the secret through Argon2 and a new secret should be used for each comparison
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chrono::{DateTime, Duration, Utc};
+use curve25519_dalek::{ristretto::CompressedRistretto, RistrettoPoint, Scalar};
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use crate::{compute::EncounterOptions, decoders::bundle::KdfParams, errors::Error, model::{SpaceTimePoint, SpaceTimeRecord}};
+
+/// Changes whenever tokens are made differently, so tokens from different versions are never compared
+pub const TOKEN_VERSION: u8 = 2;
+/// Keys are never shared so they don't need a salt of their own, this keeps them from being used for anything else
+const KEY_SALT: &[u8] = b"chance-encounters tokens";
+const KM_PER_DEGREE: f64 = 111.32;
+
+/// A compressed Ristretto point
+type Token = [u8; 32];
+
+/// Someone's own key for blinding tokens, it never leaves the device
+#[derive(Clone)]
+pub struct TokenKey(Scalar);
+
+impl TokenKey {
+    /// Derives the key from a secret with Argon2id, both steps of a comparison must use the same secret
+    pub fn from_secret(secret: &str, params: KdfParams) -> Result<Self, Error> {
+        let argon2_params = Params::new(params.memory_kib, params.iterations, 1, Some(64))
+            .map_err(|err| Error::TokenError(format!("Invalid key derivation parameters: {}", err)))?;
+        let mut wide = [0; 64];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+            .hash_password_into(secret.as_bytes(), KEY_SALT, &mut wide)
+            .map_err(|err| Error::TokenError(format!("Unable to derive a key: {}", err)))?;
+        Ok(TokenKey(Scalar::from_bytes_mod_order_wide(&wide)))
+    }
+
+    /// The token blinded again with this key, or nothing if it isn't a valid point
+    fn blind(&self, token: &Token) -> Option<Token> {
+        CompressedRistretto(*token).decompress().map(|point| (point * self.0).compress().to_bytes())
+    }
+
+    /// The other person's tokens blinded again with this key, to send back to them
+    pub fn reply(&self, other: &TokenSet) -> Result<TokenReply, Error> {
+        check_version(other.version)?;
+        let tokens = other.tokens.iter()
+            .map(|token| from_hex(token).and_then(|bytes| self.blind(&bytes))
+                .map(|blinded| [token.clone(), to_hex(&blinded)])
+                .ok_or_else(|| Error::TokenError(format!("{} isn't a valid token", token))))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(TokenReply { version: TOKEN_VERSION, tokens })
+    }
+}
+
+/// How coarse the tokens are. Both people must use the same cell size and bucket length, the margins can differ
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TokenOptions {
+    pub cell_size_m: f64,
+    pub bucket_secs: i64,
+    /// Neighbouring cells closer than this to a point get tokens too, so people either side of a cell edge still match
+    pub margin_m: f64,
+    /// Points are stretched by this much either side before finding the buckets they cover
+    pub margin_secs: i64,
+}
+
+impl Default for TokenOptions {
+    fn default() -> Self {
+        TokenOptions::from(&EncounterOptions::default())
+    }
+}
+
+impl From<&EncounterOptions> for TokenOptions {
+    /// Margins that catch every encounter the options would find, in 250m cells and 15 minute buckets
+    fn from(options: &EncounterOptions) -> Self {
+        TokenOptions {
+            cell_size_m: 250.0,
+            bucket_secs: 15 * 60,
+            margin_m: options.max_distance_km * 1000.0,
+            margin_secs: options.max_time_gap_secs,
+        }
+    }
+}
+
+/// What's shared with the other person first, only the blinded tokens and the settings needed to make matching ones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenSet {
+    pub version: u8,
+    pub cell_size_m: f64,
+    pub bucket_secs: i64,
+    /// Hex encoded and sorted, so their order says nothing about the history
+    pub tokens: Vec<String>,
+}
+
+/// The other person's tokens blinded again with the replier's key, each paired with the token it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenReply {
+    pub version: u8,
+    /// Sorted, in the order of the tokens they were sent
+    pub tokens: Vec<[String; 2]>,
+}
+
+/// A cell and time bucket both people were in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandidateEncounter {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Centre of the cell
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Grid cells are rows of equal height with columns that narrow towards the poles, so cells stay about square
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Cell {
+    row: i64,
+    column: i64,
+}
+
+impl Cell {
+    fn row_height(cell_size_m: f64) -> f64 {
+        cell_size_m / 1000.0 / KM_PER_DEGREE
+    }
+
+    /// Columns are as wide as the row is high at the row's centre, so a cell never depends on anything but its row
+    fn column_width(row: i64, cell_size_m: f64) -> f64 {
+        let row_height = Self::row_height(cell_size_m);
+        let centre_latitude = ((row as f64 + 0.5) * row_height).clamp(-89.0, 89.0);
+        row_height / centre_latitude.to_radians().cos()
+    }
+
+    pub(crate) fn at(latitude: f64, longitude: f64, cell_size_m: f64) -> Self {
+        let row = (latitude / Self::row_height(cell_size_m)).floor() as i64;
+        Cell { row, column: (longitude / Self::column_width(row, cell_size_m)).floor() as i64 }
+    }
+
+    pub(crate) fn centre(&self, cell_size_m: f64) -> (f64, f64) {
+        ((self.row as f64 + 0.5) * Self::row_height(cell_size_m), (self.column as f64 + 0.5) * Self::column_width(self.row, cell_size_m))
+    }
+
+    /// The cell a point is in and any others within the margin of it. The corners and edges of a box around the
+    /// point land in every cell the box touches as long as the margin is smaller than a cell
+    fn near(point: &SpaceTimePoint, options: &TokenOptions) -> HashSet<Cell> {
+        let margin = options.margin_m.clamp(0.0, options.cell_size_m);
+        let latitude_margin = margin / 1000.0 / KM_PER_DEGREE;
+        let longitude_margin = latitude_margin / point.latitude.to_radians().cos().max(0.01);
+        let mut cells = HashSet::new();
+        for latitude_offset in [-latitude_margin, 0.0, latitude_margin] {
+            for longitude_offset in [-longitude_margin, 0.0, longitude_margin] {
+                cells.insert(Cell::at(point.latitude + latitude_offset, point.longitude + longitude_offset, options.cell_size_m));
+            }
+        }
+        cells
+    }
+}
+
+/// Someone's own blinded tokens, along with the cell and bucket each came from so matches can be turned back into
+/// candidates. Only `export` should leave the device
+pub struct CellTokens {
+    options: TokenOptions,
+    key: TokenKey,
+    cells: BTreeMap<Token, (Cell, i64)>,
+}
+
+impl CellTokens {
+    pub fn new(record: &SpaceTimeRecord, key: &TokenKey, options: TokenOptions) -> Self {
+        let bucket_secs = options.bucket_secs.max(1);
+        let margin = Duration::seconds(options.margin_secs.max(0));
+
+        let mut cells = HashSet::new();
+        for point in &record.points {
+            let first_bucket = (point.start_time - margin).timestamp().div_euclid(bucket_secs);
+            let last_bucket = (point.end_time + margin).timestamp().div_euclid(bucket_secs);
+            for cell in Cell::near(point, &options) {
+                cells.extend((first_bucket..=last_bucket).map(|bucket| (cell, bucket)));
+            }
+        }
+        let cells = cells.into_iter()
+            .map(|(cell, bucket)| {
+                let element = RistrettoPoint::hash_from_bytes::<Sha512>(format!("chance-encounters tokens|{}|{}|{}|{}|{}",
+                    options.cell_size_m, bucket_secs, cell.row, cell.column, bucket).as_bytes());
+                ((element * key.0).compress().to_bytes(), (cell, bucket))
+            })
+            .collect();
+        CellTokens { options, key: key.clone(), cells }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn export(&self) -> TokenSet {
+        TokenSet {
+            version: TOKEN_VERSION,
+            cell_size_m: self.options.cell_size_m,
+            bucket_secs: self.options.bucket_secs,
+            tokens: self.cells.keys().map(|token| to_hex(token)).collect(),
+        }
+    }
+
+    /// The cells and times both people were in, with consecutive buckets in the same cell joined, earliest first.
+    /// Needs the other person's tokens and their reply to these tokens
+    pub fn candidates(&self, other: &TokenSet, reply: &TokenReply) -> Result<Vec<CandidateEncounter>, Error> {
+        check_version(other.version)?;
+        check_version(reply.version)?;
+        if other.cell_size_m != self.options.cell_size_m || other.bucket_secs != self.options.bucket_secs {
+            return Err(Error::TokenError(format!("tokens use {}m cells and {}s buckets, expected {}m and {}s",
+                other.cell_size_m, other.bucket_secs, self.options.cell_size_m, self.options.bucket_secs)));
+        }
+        // Our own tokens blinded with both keys
+        let both_keys: HashMap<Token, (Cell, i64)> = reply.tokens.iter()
+            .filter_map(|[token, blinded]| Some((from_hex(blinded)?, *self.cells.get(&from_hex(token)?)?)))
+            .collect();
+        let mut matched: Vec<(Cell, i64)> = other.tokens.iter()
+            .filter_map(|token| self.key.blind(&from_hex(token)?))
+            .filter_map(|token| both_keys.get(&token).copied())
+            .collect();
+        matched.sort();
+
+        let bucket_secs = self.options.bucket_secs.max(1);
+        let bucket_time = |bucket: i64| DateTime::from_timestamp(bucket * bucket_secs, 0).unwrap_or_default();
+        let mut candidates: Vec<(Cell, i64, i64)> = Vec::new();
+        for (cell, bucket) in matched {
+            match candidates.last_mut() {
+                Some((last_cell, _, last_bucket)) if *last_cell == cell && *last_bucket + 1 == bucket => *last_bucket = bucket,
+                _ => candidates.push((cell, bucket, bucket)),
+            }
+        }
+        let mut candidates: Vec<CandidateEncounter> = candidates.into_iter().map(|(cell, first_bucket, last_bucket)| {
+            let (latitude, longitude) = cell.centre(self.options.cell_size_m);
+            CandidateEncounter { start_time: bucket_time(first_bucket), end_time: bucket_time(last_bucket + 1), latitude, longitude }
+        }).collect();
+        candidates.sort_by_key(|candidate| candidate.start_time);
+        Ok(candidates)
+    }
+}
+
+fn check_version(version: u8) -> Result<(), Error> {
+    match version == TOKEN_VERSION {
+        true => Ok(()),
+        false => Err(Error::TokenError(format!("tokens are version {}, expected {}", version, TOKEN_VERSION))),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Token> {
+    let mut token = [0; 32];
+    if text.len() != token.len() * 2 || !text.is_ascii() {
+        return None;
+    }
+    for (byte, pair) in token.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_point;
+
+    fn record(points: &[(i64, i64, f64, f64)]) -> SpaceTimeRecord {
+        SpaceTimeRecord::new(points.iter().map(|(start, end, latitude, longitude)| test_point(*start, *end, *latitude, *longitude)).collect(), Vec::new())
+    }
+
+    /// Cheap key derivation so tests stay quick
+    fn key(secret: &str) -> TokenKey {
+        TokenKey::from_secret(secret, KdfParams { memory_kib: 64, iterations: 1 }).unwrap()
+    }
+
+    /// What `mine` finds after both sides swap tokens and `theirs` replies, only through what's shared
+    fn compare(mine: &CellTokens, theirs: &CellTokens) -> Result<Vec<CandidateEncounter>, Error> {
+        let reply = serde_json::to_string(&theirs.key.reply(&mine.export())?).unwrap();
+        mine.candidates(&theirs.export(), &serde_json::from_str(&reply).unwrap())
+    }
+
+    #[test]
+    fn test_candidates() {
+        let start = 1_709_373_600; // 2024-03-02 10:00 UTC, on a bucket boundary
+        // Both at a cafe for half an hour, then the second person goes home across town
+        let alice = record(&[(start, start + 1800, 52.52000, 13.40500), (start + 7200, start + 9000, 52.50000, 13.30000)]);
+        let bob = record(&[(start + 600, start + 2400, 52.52030, 13.40520), (start + 7200, start + 9000, 52.55000, 13.45000)]);
+        let options = TokenOptions::default();
+
+        // Each person has their own secret
+        let alice_tokens = CellTokens::new(&alice, &key("alice's secret"), options);
+        let bob_tokens = CellTokens::new(&bob, &key("bob's secret"), options);
+        let candidates = compare(&alice_tokens, &bob_tokens).unwrap();
+        // Each side finds the same meeting from their own tokens
+        assert_eq!(candidates, compare(&bob_tokens, &alice_tokens).unwrap());
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|candidate| candidate.end_time.timestamp() <= start + 3600));
+        assert!(candidates.iter().all(|candidate| (candidate.latitude - 52.52).abs() < 0.01));
+    }
+
+    #[test]
+    fn test_tokens_need_the_key() {
+        let history = record(&[(0, 3600, 52.52, 13.405)]);
+        let alice = CellTokens::new(&history, &key("alice's secret"), TokenOptions::default());
+        // Someone else making tokens for exactly the same cells and buckets can't recognise any of them
+        let guess = CellTokens::new(&history, &key("a guess"), TokenOptions::default());
+        let guessed: HashSet<String> = guess.export().tokens.into_iter().collect();
+        assert_eq!(alice.len(), guess.len());
+        assert!(alice.export().tokens.iter().all(|token| !guessed.contains(token)));
+        // The same secret always makes the same key
+        assert_eq!(CellTokens::new(&history, &key("alice's secret"), TokenOptions::default()).export(), alice.export());
+    }
+
+    #[test]
+    fn test_candidates_across_cell_edge() {
+        let options = TokenOptions { cell_size_m: 250.0, bucket_secs: 900, margin_m: 50.0, margin_secs: 0 };
+        // Two points 20m apart either side of a row boundary
+        let edge = (Cell::at(52.52, 13.405, options.cell_size_m).row + 1) as f64 * Cell::row_height(options.cell_size_m);
+        let tokens = |latitude: f64, secret: &str, options: TokenOptions| CellTokens::new(&record(&[(0, 60, latitude, 13.405)]), &key(secret), options);
+        assert!(!compare(&tokens(edge - 0.0001, "alice", options), &tokens(edge + 0.0001, "bob", options)).unwrap().is_empty());
+        // Without a margin each only has a token for their own cell
+        let options = TokenOptions { margin_m: 0.0, ..options };
+        assert!(compare(&tokens(edge - 0.0001, "alice", options), &tokens(edge + 0.0001, "bob", options)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_candidates_mismatched_options() {
+        let alice = CellTokens::new(&record(&[(0, 60, 52.52, 13.405)]), &key("secret"), TokenOptions::default());
+        let reply = alice.key.reply(&alice.export()).unwrap();
+        let mut other = alice.export();
+        other.bucket_secs = 3600;
+        assert!(matches!(alice.candidates(&other, &reply), Err(Error::TokenError(_))));
+        other = TokenSet { version: 1, ..alice.export() };
+        assert!(matches!(alice.candidates(&other, &reply), Err(Error::TokenError(_))));
+    }
+
+    #[test]
+    fn test_reply_rejects_invalid_tokens() {
+        let alice = CellTokens::new(&record(&[(0, 60, 52.52, 13.405)]), &key("secret"), TokenOptions::default());
+        let mut other = alice.export();
+        other.tokens.push("ff".repeat(32));
+        assert!(matches!(key("bob").reply(&other), Err(Error::TokenError(_))));
+    }
+
+    #[test]
+    fn test_export_hides_order() {
+        let tokens = CellTokens::new(&record(&[(0, 3600, 52.52, 13.405), (3600, 7200, 48.85, 2.35)]), &key("secret"), TokenOptions::default());
+        let exported = tokens.export();
+        assert_eq!(exported.tokens.len(), tokens.len());
+        assert!(exported.tokens.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(exported.tokens.iter().all(|token| from_hex(token).is_some()));
+        let reply = key("bob").reply(&exported).unwrap();
+        assert!(reply.tokens.iter().zip(&exported.tokens).all(|([token, _], exported)| token == exported));
+    }
+}
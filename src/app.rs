@@ -1,9 +1,16 @@
 use leptos::*;
 use leptos_workers::worker;
+use serde::{Deserialize, Serialize};
 use crate::errors::Error;
-use crate::decoders::*;
-use crate::model::*;
-use crate::utils::{fileutils::*, *, errors::FileProcessingError};
+use crate::map::EncounterMap;
+use crate::timeline::Timeline;
+use crate::table::EncounterTable;
+use crate::exclusions::{ExclusionSuggestions, ExclusionZoneEditor};
+use crate::sessions::RecentSessions;
+use crate::workers::*;
+use std::{cell::{Cell, RefCell}, rc::Rc};
+use chance_encounters_core::{decoders::bundle::{Bundle, KdfParams}, cache::content_hash, compute::*, export::*, exclusion::*, filter::*, model::*, places::*, timeline::*};
+use crate::utils::{fileutils::*, *, errors::FileProcessingError, sessions::*};
 
 #[component]
 pub fn App() -> impl IntoView {
@@ -24,6 +31,71 @@ fn Home(button_clicked: ReadSignal<bool>) -> impl IntoView {
     // References to the file input elements
     let file1_ref = create_node_ref::<html::Input>();
     let file2_ref = create_node_ref::<html::Input>();
+    // Bumped whenever an analysis starts or is cancelled, so messages from older runs can be told apart
+    let run = create_rw_signal(0u64);
+    let options = create_rw_signal(EncounterOptions::default());
+    let rerun = move || {
+        if button_clicked.get_untracked() {
+            run.update(|run| *run += 1);
+        }
+    };
+    // Changing a threshold after analyzing reruns the analysis, the worker still has the files decoded
+    let set_option = move |update: fn(&mut EncounterOptions, f64), value: String| {
+        let Ok(value) = value.parse::<f64>() else { return };
+        options.update(|options| update(options, value));
+        rerun();
+    };
+    // Points outside the dates and area are dropped while decoding, so changing them decodes again
+    let from_date = create_rw_signal(None::<chrono::NaiveDate>);
+    let to_date = create_rw_signal(None::<chrono::NaiveDate>);
+    let area = create_rw_signal(None::<Area>);
+    let area_text = create_rw_signal(String::new());
+    // Only needed when one of the files is an encrypted bundle
+    let passphrase = create_rw_signal(String::new());
+    let (area_error, set_area_error) = create_signal(None::<String>);
+    let filter = create_memo(move |_| PointFilter::from_dates(from_date.get(), to_date.get(), area.get()));
+    let set_date = move |date: RwSignal<Option<chrono::NaiveDate>>, value: String| {
+        date.set(value.parse().ok());
+        rerun();
+    };
+    // Like the thresholds, preparing the records again only reruns the analysis
+    let preparation = create_rw_signal(Preparation::default());
+    let set_preparation = move |update: &dyn Fn(&mut Preparation)| {
+        preparation.update(|preparation| update(preparation));
+        rerun();
+    };
+    // Exclusion zones are kept between visits since they rarely change, and leave decoding alone
+    let zones1 = create_rw_signal(storage::load::<Vec<ExclusionZone>>(EXCLUSION_ZONES_KEYS[0]).unwrap_or_default());
+    let zones2 = create_rw_signal(storage::load::<Vec<ExclusionZone>>(EXCLUSION_ZONES_KEYS[1]).unwrap_or_default());
+    create_effect(move |saved_before: Option<()>| {
+        zones1.with(|zones| storage::save(EXCLUSION_ZONES_KEYS[0], zones));
+        zones2.with(|zones| storage::save(EXCLUSION_ZONES_KEYS[1], zones));
+        if saved_before.is_some() {
+            rerun();
+        }
+    });
+    let set_area = move |value: String| {
+        area_text.set(value.clone());
+        match value.trim() {
+            "" => area.set(None),
+            value => match value.parse::<Area>() {
+                Ok(parsed) => area.set(Some(parsed)),
+                Err(error) => return set_area_error.set(Some(error)),
+            },
+        }
+        set_area_error.set(None);
+        rerun();
+    };
+    // Inputs show the current options so opening a saved session fills them in
+    let threshold_input = move |label: &'static str, value: fn(&EncounterOptions) -> f64, update: fn(&mut EncounterOptions, f64)| view! {
+        <div class="form-control w-full max-w-xs">
+            <label class="label">
+                <span class="label-text">{label}</span>
+            </label>
+            <input type="number" min="0" class="input input-bordered input-sm w-full max-w-xs" prop:value=move || options.with(value)
+                on:change=move |ev| set_option(update, event_target_value(&ev))/>
+        </div>
+    };
 
     let (file1_result, set_file1_result) = create_signal::<FileResult>(Err(FileProcessingError::InProcessError));
     let (file2_result, set_file2_result) = create_signal::<FileResult>(Err(FileProcessingError::InProcessError));
@@ -35,8 +107,49 @@ fn Home(button_clicked: ReadSignal<bool>) -> impl IntoView {
         }
     });
 
+    // Opening a saved session puts back its records and results, then shows them as if the files had just been analyzed.
+    // The load finishes outside the component, so the context signals are looked up here
+    let set_error_messages = expect_context::<WriteSignal<ErrorMessages>>();
+    let set_button_clicked = expect_context::<WriteSignal<bool>>();
+    let sessions_changed = create_rw_signal(0u64);
+    let open_session = Callback::new(move |id: String| spawn_local(async move {
+        let Session { files: [stored1, stored2], options: session_options, filter: session_filter, preparation: session_preparation, zones: session_zones, output, .. } = match load_session(&id).await {
+            Ok(session) => session,
+            Err(err) => return set_error_messages.update(|messages| messages.push(Error::from(err))),
+        };
+        // The content isn't kept, the records are enough until the dates or area change
+        let file_content = |stored: &StoredFile| FileContent { filename: stored.filename.clone(), hash: stored.hash, content: Vec::new() };
+        let (file1, file2) = (file_content(&stored1), file_content(&stored2));
+        let settings = AnalysisSettings { options: session_options, filter: session_filter, preparation: session_preparation, zones: session_zones };
+        cache_analysis(analysis_key(&file1, &file2, &settings), output);
+        if let Err(err) = residents() {
+            return set_error_messages.update(|messages| messages.push(err));
+        }
+        for stored in [&stored1, &stored2] {
+            keep_resident(settings.filter.cache_key(stored.hash), &stored.record, &stored.source_format, None);
+        }
+        let [session_zones1, session_zones2] = settings.zones;
+        batch(|| {
+            options.set(settings.options);
+            preparation.set(settings.preparation);
+            from_date.set(settings.filter.start_time.map(|time| time.date_naive()));
+            to_date.set(settings.filter.end_time.map(|time| (time - chrono::Duration::days(1)).date_naive()));
+            area_text.set(settings.filter.area.as_ref().map(Area::to_string).unwrap_or_default());
+            area.set(settings.filter.area);
+            set_area_error.set(None);
+            zones1.set(session_zones1);
+            zones2.set(session_zones2);
+            set_file1_result.set(Ok(file1));
+            set_file2_result.set(Ok(file2));
+            set_error_messages.update(|messages| messages.clear());
+            run.update(|run| *run += 1);
+            set_button_clicked.set(true);
+        });
+    }));
+
     // Load files when the button is clicked
     let load_files = move |_| {
+        run.update(|run| *run += 1);
         set_file1_result.set(Err(FileProcessingError::InProcessError));
         set_file2_result.set(Err(FileProcessingError::InProcessError));
         clear_error_messages();
@@ -58,8 +171,9 @@ fn Home(button_clicked: ReadSignal<bool>) -> impl IntoView {
                 "Location History Analyzer"
             </h1>
             <p class="mb-4">
-                "Upload two Google location history files to find the closest spatial and temporal points."
+                "Upload two location history files (Google JSON, GPX, FIT, TCX, NMEA, OwnTracks, Overland, saved .cer records or encrypted .ceb bundles) to find the closest spatial and temporal points."
             </p>
+            <RecentSessions changed=sessions_changed on_open=open_session/>
             <div class="flex space-x-4 mb-4">
                 <div class="form-control w-full max-w-xs">
                     <label class="label">
@@ -73,71 +187,584 @@ fn Home(button_clicked: ReadSignal<bool>) -> impl IntoView {
                     </label>
                     <input type="file" class="file-input file-input-bordered w-full max-w-xs" node_ref={file2_ref} />
                 </div>
+                <div class="form-control w-full max-w-xs">
+                    <label class="label">
+                        <span class="label-text">"Bundle passphrase"</span>
+                    </label>
+                    <input type="password" class="input input-bordered w-full max-w-xs" autocomplete="off"
+                        title="Opens either file if it's an encrypted bundle"
+                        on:input=move |ev| passphrase.set(event_target_value(&ev))/>
+                </div>
+            </div>
+            <div class="flex space-x-4 mb-4">
+                {threshold_input("Max distance (m)", |options| options.max_distance_km * 1000.0, |options, value| options.max_distance_km = value / 1000.0)}
+                {threshold_input("Max time gap (s)", |options| options.max_time_gap_secs as f64, |options, value| options.max_time_gap_secs = value as i64)}
+                {threshold_input("Episode gap (s)", |options| options.episode_gap_secs as f64, |options, value| options.episode_gap_secs = value as i64)}
+                <div class="form-control w-full max-w-xs">
+                    <label class="label cursor-pointer justify-start space-x-2" title="Leaves out points that would mean moving impossibly fast">
+                        <input type="checkbox" class="checkbox checkbox-sm" prop:checked=move || preparation.with(|preparation| preparation.drop_outliers)
+                            on:change=move |ev| {
+                                let checked = event_target_checked(&ev);
+                                set_preparation(&|preparation| preparation.drop_outliers = checked);
+                            }/>
+                        <span class="label-text">"Remove outliers"</span>
+                    </label>
+                </div>
+                <div class="form-control w-full max-w-xs">
+                    <label class="label">
+                        <span class="label-text">"Simplify (m)"</span>
+                    </label>
+                    <input type="number" min="0" class="input input-bordered input-sm w-full max-w-xs" placeholder="Off"
+                        title="Thins out points to within this distance of the path, leave empty to keep every point"
+                        prop:value=move || preparation.with(|preparation| preparation.simplify_m.map(|meters| meters.to_string()).unwrap_or_default())
+                        on:change=move |ev| {
+                            let simplify_m = match event_target_value(&ev).trim() {
+                                "" => None,
+                                value => match value.parse::<f64>() {
+                                    Ok(meters) if meters >= 0.0 => Some(meters),
+                                    _ => return,
+                                },
+                            };
+                            set_preparation(&|preparation| preparation.simplify_m = simplify_m);
+                        }/>
+                </div>
+            </div>
+            <div class="flex space-x-4 mb-4">
+                <div class="form-control w-full max-w-xs">
+                    <label class="label">
+                        <span class="label-text">"From"</span>
+                    </label>
+                    <input type="date" class="input input-bordered input-sm w-full max-w-xs"
+                        prop:value=move || from_date.get().map(|date| date.to_string()).unwrap_or_default()
+                        on:change=move |ev| set_date(from_date, event_target_value(&ev))/>
+                </div>
+                <div class="form-control w-full max-w-xs">
+                    <label class="label">
+                        <span class="label-text">"To"</span>
+                    </label>
+                    <input type="date" class="input input-bordered input-sm w-full max-w-xs"
+                        prop:value=move || to_date.get().map(|date| date.to_string()).unwrap_or_default()
+                        on:change=move |ev| set_date(to_date, event_target_value(&ev))/>
+                </div>
+                <div class="form-control w-full max-w-xs">
+                    <label class="label">
+                        <span class="label-text">"Area"</span>
+                    </label>
+                    <input type="text" class="input input-bordered input-sm w-full max-w-xs"
+                        class:input-error=move || area_error.get().is_some()
+                        placeholder="min lat,min lon,max lat,max lon"
+                        title="A bounding box, or a polygon as lat,lon;lat,lon;lat,lon..."
+                        prop:value=move || area_text.get()
+                        on:change=move |ev| set_area(event_target_value(&ev))/>
+                    <label class="label">
+                        <span class="label-text-alt text-error">{move || area_error.get()}</span>
+                    </label>
+                </div>
+            </div>
+            <div class="flex space-x-4 mb-4">
+                <ExclusionZoneEditor title="Excluded places, file 1" zones=zones1/>
+                <ExclusionZoneEditor title="Excluded places, file 2" zones=zones2/>
             </div>
             <button class="btn btn-primary" on:click={load_files}> "Analyze" </button>
             <Show when=move || button_clicked.get()>
-                <ResultDisplay file_contents/>
+                // A fresh ResultDisplay per run so nothing from a cancelled run is carried over
+                {move || {
+                    run.track();
+                    view! { <ResultDisplay file_contents options=options.get_untracked() filter=filter.get_untracked() preparation=preparation.get_untracked() passphrase=passphrase.get_untracked() zones1 zones2 run sessions_changed/> }
+                }}
             </Show>
         </div>
     }
 }
 
-#[worker(MyFutureWorker)]
-pub async fn process_data(files: FileContents) -> Result<String, Error>
-{
+/// How much of each person's track is shown either side of an encounter
+const ENCOUNTER_TRACK_PADDING_MINUTES: i64 = 30;
+
+/// An encounter along with both people's tracks around it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EncounterView {
+    pub encounter: Encounter,
+    pub track1: Vec<SpaceTimePoint>,
+    pub track2: Vec<SpaceTimePoint>,
+    /// Which of either person's frequent places it happened at, if any
+    pub place: Option<String>,
+}
+
+impl EncounterView {
+    pub fn new(encounter: Encounter, record1: &SpaceTimeRecord, record2: &SpaceTimeRecord, places1: &[Place], places2: &[Place]) -> Self {
+        let padding = chrono::Duration::minutes(ENCOUNTER_TRACK_PADDING_MINUTES);
+        let (start_time, end_time) = (encounter.start_time - padding, encounter.end_time + padding);
+        let place = [("File 1", places1), ("File 2", places2)].into_iter().find_map(|(person, places)| {
+            place_at(places, encounter.latitude, encounter.longitude).map(|place| format!("{} {}", person, place.kind.to_string().to_lowercase()))
+        });
+        EncounterView {
+            track1: record1.points_between(start_time, end_time).to_vec(),
+            track2: record2.points_between(start_time, end_time).to_vec(),
+            place,
+            encounter,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AnalysisOutput {
+    pub summary: String,
+    pub filename1: String,
+    pub filename2: String,
+    pub encounters: Vec<EncounterView>,
+    pub timeline1: Vec<TimelineSegment>,
+    pub timeline2: Vec<TimelineSegment>,
+    /// Where each person spends most of their time, which they might want to exclude
+    pub places1: Vec<Place>,
+    pub places2: Vec<Place>,
+}
+
+/// Steps of an analysis in the order they run
+#[derive(Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum AnalysisPhase {
+    ParsingFiles,
+    BuildingIndex,
+    Matching,
+}
+
+const ANALYSIS_PHASES: [AnalysisPhase; 3] = [AnalysisPhase::ParsingFiles, AnalysisPhase::BuildingIndex, AnalysisPhase::Matching];
+
+impl AnalysisPhase {
+    fn name(&self) -> &'static str {
+        match self {
+            AnalysisPhase::ParsingFiles => "Parsing files",
+            AnalysisPhase::BuildingIndex => "Building index",
+            AnalysisPhase::Matching => "Matching",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisProgress {
+    pub phase: AnalysisPhase,
+    /// How much of the phase is done, from 0 to 1
+    pub fraction: f64,
+}
+
+impl AnalysisProgress {
+    /// How much of the whole analysis is done, from 0 to 100
+    fn percent(&self) -> f64 {
+        let phase_index = ANALYSIS_PHASES.iter().position(|phase| *phase == self.phase).unwrap_or_default();
+        (phase_index as f64 + self.fraction) / ANALYSIS_PHASES.len() as f64 * 100.0
+    }
+}
+
+/// A decoded record to encrypt, in a worker as deriving the key takes long enough to freeze the page
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BundleRequest {
+    pub record: SpaceTimeRecord,
+    pub person: String,
+    pub source_format: String,
+    pub passphrase: String,
+    /// Workers can't reach `window.crypto`, so the salt is made before sending
+    pub salt: [u8; Bundle::SALT_LENGTH],
+}
+
+#[worker(BundleWorker)]
+pub async fn seal_bundle(request: BundleRequest) -> Result<Vec<u8>, Error> {
+    Ok(Bundle::seal(&request.record, &request.person, &request.source_format, &request.passphrase, request.salt, KdfParams::default())?)
+}
+
+/// One run of an analysis, which stops counting once another starts or it's cancelled
+#[derive(Clone)]
+struct AnalysisRun {
+    run: RwSignal<u64>,
+    this_run: u64,
+    finished: Rc<Cell<bool>>,
+}
+
+impl AnalysisRun {
+    fn new(run: RwSignal<u64>) -> Self {
+        AnalysisRun { run, this_run: run.get_untracked(), finished: Rc::default() }
+    }
+
+    /// Whether the results are still wanted, nothing from an older run is shown or kept
+    fn is_current(&self) -> bool {
+        self.run.get_untracked() == self.this_run
+    }
+
+    /// Stop the workers if the run is still using them, they're left alone once it's done
+    fn cancel(&self) {
+        if !self.finished.replace(true) {
+            terminate_residents();
+        }
+    }
+}
+
+/// Local storage keys for each file's exclusion zones
+const EXCLUSION_ZONES_KEYS: [&str; 2] = ["exclusion_zones_1", "exclusion_zones_2"];
+/// How many analyses are kept, so opening a saved session or going back to earlier settings doesn't match again
+const ANALYSIS_CACHE_CAPACITY: usize = 4;
+
+thread_local! {
+    // Least recently added first
+    static ANALYSIS_CACHE: RefCell<Vec<(u64, AnalysisOutput)>> = RefCell::new(Vec::with_capacity(ANALYSIS_CACHE_CAPACITY));
+}
+
+/// Everything besides the files that goes into an analysis
+#[derive(Clone)]
+struct AnalysisSettings {
+    options: EncounterOptions,
+    filter: PointFilter,
+    preparation: Preparation,
+    zones: [Vec<ExclusionZone>; 2],
+}
+
+/// Identifies an analysis by everything that goes into it
+fn analysis_key(file1: &FileContent, file2: &FileContent, settings: &AnalysisSettings) -> u64 {
+    let AnalysisSettings { options, filter, preparation, zones } = settings;
+    content_hash(format!("{}{}{:?}{:?}{:?}", filter.cache_key(file1.hash), filter.cache_key(file2.hash), options, preparation, zones).as_bytes())
+}
+
+/// The same two files replace their earlier session
+fn session_id(file1: &FileContent, file2: &FileContent) -> String {
+    format!("{:016x}", content_hash(format!("{}{}", file1.hash, file2.hash).as_bytes()))
+}
+
+fn cached_analysis(key: u64) -> Option<AnalysisOutput> {
+    ANALYSIS_CACHE.with_borrow(|cache| cache.iter().find(|(entry_key, _)| *entry_key == key).map(|(_, output)| output.clone()))
+}
+
+fn cache_analysis(key: u64, output: AnalysisOutput) {
+    ANALYSIS_CACHE.with_borrow_mut(|cache| {
+        cache.retain(|(entry_key, _)| *entry_key != key);
+        if cache.len() >= ANALYSIS_CACHE_CAPACITY {
+            cache.remove(0);
+        }
+        cache.push((key, output));
+    });
+}
+
+fn unexpected_reply() -> Error {
+    Error::WebWorkerError("unexpected reply".to_string())
+}
+
+/// Make sure every worker has the file's record, decoding it in `worker` unless it's been decoded with the same
+/// filter before. A file from a saved session has no content, its record is put back from the session instead
+async fn resident_record(file: &FileContent, filter: &PointFilter, passphrase: &str, session: &str, worker: &Resident, worker_index: usize, run: &AnalysisRun) -> Result<u64, Error> {
+    let key = filter.cache_key(file.hash);
+    if is_resident(key) {
+        logging::log!("{}: using cached decode", file.filename);
+        return Ok(key);
+    }
+    if file.content.is_empty() {
+        // The workers lose their records when an analysis is cancelled
+        let session = load_session(session).await?;
+        let stored = session.files.into_iter().find(|stored| filter.cache_key(stored.hash) == key)
+            .ok_or_else(|| Error::from(FileProcessingError::SessionFileMissing(file.filename.clone())))?;
+        if run.is_current() {
+            keep_resident(key, &stored.record, &stored.source_format, None);
+        }
+        return Ok(key);
+    }
+    let request = FileRequest { filename: file.filename.clone(), content: file.content.clone(), filter: filter.clone(), passphrase: passphrase.to_string() };
+    let (record, source_format) = match worker.ask(AnalysisRequest::Decode { key, file: request }, |_| ()).await? {
+        AnalysisReply::Decoded(decoded) => decoded?,
+        _ => return Err(unexpected_reply()),
+    };
+    for warning in &record.warnings {
+        logging::warn!("{}: {}", file.filename, warning);
+    }
+    match run.is_current() {
+        true => keep_resident(key, &record, &source_format, Some(worker_index)),
+        false => worker.tell(AnalysisRequest::Forget { key }),
+    }
+    Ok(key)
+}
+
+/// Decode both files at once, then match them in time range shards spread over the workers, which keep the records
+/// so only the options are sent when rerunning
+async fn run_analysis(files: FileContents, settings: AnalysisSettings, passphrase: String, run: &AnalysisRun, progress: impl Fn(AnalysisProgress) + Clone + 'static) -> Result<AnalysisOutput, Error> {
     let (file1, file2) = match files {
         Some((file1, file2)) => (file1, file2),
         None => return Err(Error::from(FileProcessingError::MissingFileError))
     };
-    logging::log!("Running WebWorker...");
-    let record1 = SpaceTimeRecord::new(&file1.content, FileFormat::Json)?;
-    let record2 = SpaceTimeRecord::new(&file2.content, FileFormat::Json)?;
+    let key = analysis_key(&file1, &file2, &settings);
+    let AnalysisSettings { options, filter, preparation, zones } = settings;
+    if let Some(output) = cached_analysis(key) {
+        logging::log!("Using cached analysis");
+        return Ok(output);
+    }
+    let started = js_sys::Date::now();
+    let workers = residents()?;
+    let session = session_id(&file1, &file2);
+    progress(AnalysisProgress { phase: AnalysisPhase::ParsingFiles, fraction: 0.0 });
+    let decode_worker = workers.len().min(2) - 1;
+    let keys = futures::try_join!(
+        resident_record(&file1, &filter, &passphrase, &session, &workers[0], 0, run),
+        resident_record(&file2, &filter, &passphrase, &session, &workers[decode_worker], decode_worker, run),
+    )?;
+    let keys = [keys.0, keys.1];
+    let decoded = js_sys::Date::now();
 
-    Ok(format!("Data Processed. File1 {}, File2 {}", record1.points.len(), record2.points.len()))
-    // TODO: Implement actual analysis logic here
+    let shard_count = workers.len();
+    // Whether each shard has built its index and how far through matching it is, reported together as one
+    let shard_progress = Rc::new(RefCell::new(vec![(false, 0.0); shard_count]));
+    progress(AnalysisProgress { phase: AnalysisPhase::BuildingIndex, fraction: 0.0 });
+    let shard_results = futures::future::join_all(workers.iter().enumerate().map(|(shard, worker)| {
+        let (shard_progress, progress) = (shard_progress.clone(), progress.clone());
+        let request = AnalysisRequest::Match { keys, preparation, zones: zones.clone(), options, shard, shard_count };
+        worker.ask(request, move |reply| {
+            let mut shard_progress = shard_progress.borrow_mut();
+            match reply {
+                AnalysisReply::Indexed => shard_progress[shard].0 = true,
+                AnalysisReply::Matching(fraction) => shard_progress[shard].1 = fraction,
+                _ => (),
+            }
+            let indexed = shard_progress.iter().filter(|(indexed, _)| *indexed).count();
+            progress(match indexed < shard_count {
+                true => AnalysisProgress { phase: AnalysisPhase::BuildingIndex, fraction: indexed as f64 / shard_count as f64 },
+                false => AnalysisProgress { phase: AnalysisPhase::Matching, fraction: shard_progress.iter().map(|(_, fraction)| fraction).sum::<f64>() / shard_count as f64 },
+            });
+        })
+    })).await;
+    let mut encounters = Vec::new();
+    for result in shard_results {
+        match result? {
+            AnalysisReply::Matched(shard_encounters) => encounters.extend(shard_encounters?),
+            _ => return Err(unexpected_reply()),
+        }
+    }
+    let matched = js_sys::Date::now();
+
+    let request = AnalysisRequest::Summarize { keys, preparation, zones, options, encounters, filenames: [file1.filename, file2.filename] };
+    let output = match workers[0].ask(request, |_| ()).await? {
+        AnalysisReply::Summarized(output) => output?,
+        _ => return Err(unexpected_reply()),
+    };
+    logging::log!("Decoded in {:.0}ms, matched {} shards in {:.0}ms, summarized in {:.0}ms", decoded - started, shard_count, matched - decoded, js_sys::Date::now() - matched);
+    if run.is_current() {
+        cache_analysis(key, output.clone());
+    }
+    Ok(output)
+}
+
+/// Keep an analysis in the browser so it can be opened again without the files, saved by a worker as it has the records
+fn save_analysis(files: FileContents, settings: AnalysisSettings, output: &AnalysisOutput, sessions_changed: RwSignal<u64>) {
+    let Some((file1, file2)) = files else { return };
+    let AnalysisSettings { options, filter, preparation, zones } = settings;
+    // Both records were decoded or restored for this analysis, unless the workers have moved on since
+    let keys = [filter.cache_key(file1.hash), filter.cache_key(file2.hash)];
+    let Ok(workers) = residents() else { return };
+    if !keys.iter().all(|key| is_resident(*key)) {
+        return;
+    }
+    let request = SaveRequest {
+        id: session_id(&file1, &file2),
+        keys,
+        filenames: [file1.filename, file2.filename],
+        hashes: [file1.hash, file2.hash],
+        options,
+        filter,
+        zones,
+        preparation,
+        output: output.clone(),
+    };
+    spawn_local(async move {
+        match workers[0].ask(AnalysisRequest::Save(Box::new(request)), |_| ()).await {
+            Ok(AnalysisReply::Saved(Ok(()))) => sessions_changed.update(|changed| *changed += 1),
+            Ok(AnalysisReply::Saved(Err(err))) | Err(err) => logging::warn!("{}", err),
+            Ok(_) => logging::warn!("{}", unexpected_reply()),
+        }
+    });
 }
 
 #[component]
-fn ResultDisplay(file_contents: Memo<Option<(FileContent, FileContent)>>) -> impl IntoView {
+fn ResultDisplay(file_contents: Memo<FileContents>, options: EncounterOptions, filter: PointFilter, preparation: Preparation, passphrase: String, zones1: RwSignal<Vec<ExclusionZone>>, zones2: RwSignal<Vec<ExclusionZone>>, run: RwSignal<u64>, sessions_changed: RwSignal<u64>) -> impl IntoView {
+    let (progress, set_progress) = create_signal::<Option<AnalysisProgress>>(None);
+    let analysis_run = store_value(AnalysisRun::new(run));
+    // Cancelling, or starting another run, replaces this component, which stops whatever it left running
+    let cleanup_run = analysis_run.get_value();
+    on_cleanup(move || cleanup_run.cancel());
+    let bundle_filter = store_value(filter.clone());
     let response = create_local_resource(|| {}, move |_| {
-        process_data(file_contents.get())
+        let settings = AnalysisSettings { options, filter: filter.clone(), preparation, zones: [zones1.get_untracked(), zones2.get_untracked()] };
+        let (files, passphrase) = (file_contents.get(), passphrase.clone());
+        let analysis_run = analysis_run.get_value();
+        async move {
+            let current = analysis_run.clone();
+            let output = run_analysis(files.clone(), settings.clone(), passphrase, &analysis_run, move |progress| {
+                if current.is_current() {
+                    set_progress.set(Some(progress));
+                }
+            }).await;
+            analysis_run.finished.set(true);
+            let output = output?;
+            // A run cancelled as it finished isn't kept, the person asked for it not to be
+            if analysis_run.is_current() {
+                save_analysis(files, settings, &output, sessions_changed);
+            }
+            Ok::<_, Error>(output)
+        }
     });
+    let cancel = move |_| {
+        run.update(|run| *run += 1);
+        set_processing(false);
+    };
     view! {
         {move || match response.get() {
-            None => view! { <LoadingSpinner /> },
-            Some(result) => {
-                match result {
-                    Ok(analysis_result) => match analysis_result {
-                        Ok(analysis_result) => view! { <AnalysisResult analysis_result/> },
-                        Err(error) => {
-                            match error {
-                                Error::FileProcessingError(FileProcessingError::MissingFileError) => {},
-                                _ => end_processing(error)
-                            }
-                            // This won't be reached as ResultDisplay is hidden when end_processing is called
-                            view! { <LoadingSpinner /> }
-                        }
-                    },
-                    Err(error) => {
-                        end_processing(Error::WebWorkerError(error.to_string()));
-                        // This won't be reached as ResultDisplay is hidden when end_processing is called
-                        view! { <LoadingSpinner /> }
-                    }
+            Some(Ok(analysis_result)) => view! { <AnalysisResult analysis_result file_contents filter=bundle_filter.get_value() zones1 zones2/> }.into_view(),
+            Some(Err(error)) => {
+                match error {
+                    Error::FileProcessingError(FileProcessingError::MissingFileError) => {},
+                    _ => end_processing(error)
                 }
-            }
+                // This won't be reached as ResultDisplay is hidden when end_processing is called
+                view! { <LoadingSpinner /> }.into_view()
+            },
+            None => view! {
+                <ProgressDisplay progress/>
+                <div class="flex justify-center mt-2">
+                    <button class="btn btn-sm btn-outline" on:click=cancel>"Cancel"</button>
+                </div>
+            }.into_view(),
         }}
     }
 }
 
 #[component]
-fn AnalysisResult(analysis_result: String) -> impl IntoView {
+fn ProgressDisplay(progress: ReadSignal<Option<AnalysisProgress>>) -> impl IntoView {
+    let label = move || match progress.get() {
+        Some(AnalysisProgress { phase: AnalysisPhase::Matching, fraction }) => format!("{} {:.0}%", AnalysisPhase::Matching.name(), fraction * 100.0),
+        Some(progress) => progress.phase.name().to_string(),
+        None => "Starting worker".to_string(),
+    };
+    view! {
+        <div class="flex flex-col items-center w-full mt-4">
+            <ul class="steps mb-2">
+                {ANALYSIS_PHASES.into_iter().map(|phase| view! {
+                    <li class={move || if progress.get().is_some_and(|progress| progress.phase >= phase) { "step step-primary" } else { "step" }}>{phase.name()}</li>
+                }).collect_view()}
+            </ul>
+            <progress class="progress progress-primary w-full max-w-md" value={move || progress.get().map(|progress| progress.percent()).unwrap_or_default()} max="100"></progress>
+            <span class="text-sm mt-1">{label}</span>
+        </div>
+    }
+}
+
+#[component]
+fn AnalysisResult(analysis_result: AnalysisOutput, file_contents: Memo<FileContents>, filter: PointFilter, zones1: RwSignal<Vec<ExclusionZone>>, zones2: RwSignal<Vec<ExclusionZone>>) -> impl IntoView {
+    // The map, timeline and table all show the same selected encounter
+    let selected = create_rw_signal(0usize);
+    let encounters: Vec<Encounter> = analysis_result.encounters.iter().map(|view| view.encounter.clone()).collect();
+    let places: Vec<Option<String>> = analysis_result.encounters.iter().map(|view| view.place.clone()).collect();
     view! {
         <div class="mt-4 w-full">
         <h2 class="text-xl font-bold mb-2">"Analysis Results"</h2>
-            <h3 class="text-lg font-semibold mb-2">"File 1 Data"</h3>
-            <div class="mockup-code h-64 overflow-auto">
-                <pre><code>{analysis_result}</code></pre>
-            </div>
+            <p class="mb-4 text-sm">{analysis_result.summary.clone()}</p>
+            <ExclusionSuggestions filename=analysis_result.filename1.clone() places=analysis_result.places1.clone() zones=zones1/>
+            <ExclusionSuggestions filename=analysis_result.filename2.clone() places=analysis_result.places2.clone() zones=zones2/>
+            <ExportButtons analysis_result=analysis_result.clone()/>
+            <BundleExport file_contents filter/>
+            <EncounterMap encounters=analysis_result.encounters selected/>
+            <Timeline timeline1=analysis_result.timeline1 timeline2=analysis_result.timeline2 encounters=encounters.clone() selected/>
+            <EncounterTable encounters places selected/>
+        </div>
+    }
+}
+
+type ExportWriter = fn(&mut Vec<u8>, &[EncounterRow]) -> std::io::Result<()>;
+
+/// Formats the results can be downloaded as: button label, file extension, mime type and writer
+const EXPORT_FORMATS: [(&str, &str, &str, ExportWriter); 4] = [
+    ("CSV", "csv", "text/csv", |out, rows| write_csv(out, rows)),
+    ("JSON", "json", "application/json", |out, rows| write_json(out, rows)),
+    ("GeoJSON", "geojson", "application/geo+json", |out, rows| write_geojson(out, rows)),
+    ("Calendar", "ics", "text/calendar", |out, rows| write_ics(out, rows)),
+];
+
+#[component]
+fn ExportButtons(analysis_result: AnalysisOutput) -> impl IntoView {
+    let analysis_result = store_value(analysis_result);
+    let export = move |extension: &'static str, mime_type: &'static str, write: fn(&mut Vec<u8>, &[EncounterRow]) -> std::io::Result<()>| analysis_result.with_value(|analysis_result| {
+        let rows: Vec<EncounterRow> = analysis_result.encounters.iter().map(|view| EncounterRow {
+            person1: &analysis_result.filename1,
+            person2: &analysis_result.filename2,
+            encounter: view.encounter.clone(),
+            track1: &view.track1,
+            track2: &view.track2,
+        }).collect();
+        let mut content = Vec::new();
+        let downloaded = write(&mut content, &rows)
+            .map_err(|err| FileProcessingError::DownloadError(err.to_string()))
+            .and_then(|_| download_file(&format!("encounters.{}", extension), mime_type, &content));
+        if let Err(err) = downloaded {
+            log_error(Error::from(err));
+        }
+    });
+
+    view! {
+        <div class="flex items-center gap-2 mb-4">
+            <span class="text-sm">"Export"</span>
+            {EXPORT_FORMATS.into_iter().map(|(label, extension, mime_type, write)| view! {
+                <button class="btn btn-sm btn-outline" on:click=move |_| export(extension, mime_type, write)>{label}</button>
+            }).collect_view()}
+        </div>
+    }
+}
+
+/// Passphrases shorter than this are too easy to guess for something sent over chat
+const MIN_PASSPHRASE_LENGTH: usize = 8;
+
+/// Encrypt either person's decoded history so it can be sent to someone, only the encrypted bundle leaves the browser
+#[component]
+fn BundleExport(file_contents: Memo<FileContents>, filter: PointFilter) -> impl IntoView {
+    let passphrase = create_rw_signal(String::new());
+    let (sealing, set_sealing) = create_signal(false);
+    // Sealing finishes outside the component, so the error context is looked up here
+    let set_error_messages = expect_context::<WriteSignal<ErrorMessages>>();
+    let filter = store_value(filter);
+    let export = move |file: FileContent| {
+        let key = filter.with_value(|filter| filter.cache_key(file.hash));
+        let worker = match residents() {
+            Ok(workers) if is_resident(key) => workers[0].clone(),
+            Ok(_) => return set_error_messages.update(|messages| messages.push(Error::from(FileProcessingError::SessionFileMissing(file.filename.clone())))),
+            Err(err) => return set_error_messages.update(|messages| messages.push(err)),
+        };
+        let mut salt = [0; Bundle::SALT_LENGTH];
+        if window().crypto().and_then(|crypto| crypto.get_random_values_with_u8_array(&mut salt)).is_err() {
+            return set_error_messages.update(|messages| messages.push(Error::from(FileProcessingError::DownloadError(format!("{}: no secure random numbers for the bundle", file.filename)))));
+        }
+        let person = file.filename.rsplit_once('.').map_or(file.filename.as_str(), |(stem, _)| stem).to_string();
+        let passphrase = passphrase.get_untracked();
+        set_sealing.set(true);
+        spawn_local(async move {
+            let downloaded = async {
+                let (record, source_format) = match worker.ask(AnalysisRequest::Fetch { key }, |_| ()).await? {
+                    AnalysisReply::Fetched(fetched) => fetched?,
+                    _ => return Err(unexpected_reply()),
+                };
+                let request = BundleRequest { record, person: person.clone(), source_format, passphrase, salt };
+                let content = seal_bundle(request).await.map_err(|error| Error::WebWorkerError(error.to_string()))??;
+                download_file(&format!("{}.{}", person, Bundle::EXTENSION), "application/octet-stream", &content)?;
+                Ok::<_, Error>(())
+            }.await;
+            set_sealing.set(false);
+            if let Err(err) = downloaded {
+                set_error_messages.update(|messages| messages.push(err));
+            }
+        });
+    };
+    let disabled = move || sealing.get() || passphrase.with(|passphrase| passphrase.chars().count() < MIN_PASSPHRASE_LENGTH);
+
+    view! {
+        <div class="flex items-center gap-2 mb-4">
+            <span class="text-sm">"Encrypted bundle"</span>
+            <input type="password" class="input input-bordered input-sm" autocomplete="new-password"
+                placeholder=format!("Passphrase, at least {} characters", MIN_PASSPHRASE_LENGTH)
+                on:input=move |ev| passphrase.set(event_target_value(&ev))/>
+            {move || file_contents.get().map(|(file1, file2)| [file1, file2].into_iter().map(|file| {
+                let filename = file.filename.clone();
+                view! { <button class="btn btn-sm btn-outline" disabled=disabled on:click=move |_| export(file.clone())>{filename}</button> }
+            }).collect_view())}
+            <Show when=move || sealing.get()>
+                <span class="loading loading-spinner loading-sm"></span>
+            </Show>
         </div>
     }
 }
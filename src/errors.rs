@@ -1,11 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{decoders::errors::DecoderError, utils::errors::FileProcessingError};
+use crate::utils::errors::FileProcessingError;
+use chance_encounters_core::{decoders::errors::DecoderError, errors::Error as CoreError};
 use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Error {
-    DecoderError(DecoderError),
+    CoreError(CoreError),
     FileProcessingError(FileProcessingError),
     WebWorkerError(String),
 }
@@ -13,16 +14,22 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::DecoderError(err) => write!(f, "Decoder error: {}", err),
+            Error::CoreError(err) => write!(f, "{}", err),
             Error::FileProcessingError(err) => write!(f, "File processing error: {}", err),
             Error::WebWorkerError(err) => write!(f, "Web worker error: {}", err),
         }
     }
 }
 
+impl From<CoreError> for Error {
+    fn from(err: CoreError) -> Error {
+        Error::CoreError(err)
+    }
+}
+
 impl From<DecoderError> for Error {
     fn from(err: DecoderError) -> Error {
-        Error::DecoderError(err)
+        Error::CoreError(CoreError::from(err))
     }
 }
 
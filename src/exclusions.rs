@@ -0,0 +1,79 @@
+use leptos::*;
+use chance_encounters_core::{exclusion::{ExclusionZone, DEFAULT_ZONE_RADIUS_M}, places::Place};
+
+/// A person's exclusion zones, with inputs for adding one by hand
+#[component]
+pub fn ExclusionZoneEditor(title: &'static str, zones: RwSignal<Vec<ExclusionZone>>) -> impl IntoView {
+    let (label, set_label) = create_signal(String::new());
+    let (latitude, set_latitude) = create_signal(String::new());
+    let (longitude, set_longitude) = create_signal(String::new());
+    let (radius, set_radius) = create_signal(DEFAULT_ZONE_RADIUS_M.to_string());
+    let zone = move || Some(ExclusionZone {
+        label: match label.get().trim() {
+            "" => "Excluded".to_string(),
+            label => label.to_string(),
+        },
+        latitude: latitude.get().trim().parse().ok().filter(|latitude: &f64| (-90.0..=90.0).contains(latitude))?,
+        longitude: longitude.get().trim().parse().ok().filter(|longitude: &f64| (-180.0..=180.0).contains(longitude))?,
+        radius_m: radius.get().trim().parse().ok().filter(|radius: &f64| *radius > 0.0)?,
+    });
+    let add = move |_| {
+        if let Some(zone) = zone() {
+            zones.update(|zones| zones.push(zone));
+            set_label.set(String::new());
+        }
+    };
+
+    view! {
+        <div class="w-full max-w-xs">
+            <h3 class="text-sm font-bold mb-1">{title}</h3>
+            <ul class="text-sm mb-1">
+                {move || zones.get().into_iter().enumerate().map(|(index, zone)| view! {
+                    <li class="flex items-center justify-between">
+                        <span>{format!("{}: {:.5}, {:.5} ({:.0}m)", zone.label, zone.latitude, zone.longitude, zone.radius_m)}</span>
+                        <button class="btn btn-xs btn-ghost" title="Remove"
+                            on:click=move |_| zones.update(|zones| { zones.remove(index); })>"✕"</button>
+                    </li>
+                }).collect_view()}
+            </ul>
+            <div class="flex gap-1">
+                <input type="text" class="input input-bordered input-xs w-20" placeholder="Label"
+                    prop:value=label on:input=move |ev| set_label.set(event_target_value(&ev))/>
+                <input type="number" step="any" class="input input-bordered input-xs w-20" placeholder="Lat"
+                    prop:value=latitude on:input=move |ev| set_latitude.set(event_target_value(&ev))/>
+                <input type="number" step="any" class="input input-bordered input-xs w-20" placeholder="Lon"
+                    prop:value=longitude on:input=move |ev| set_longitude.set(event_target_value(&ev))/>
+                <input type="number" min="1" class="input input-bordered input-xs w-16" title="Radius (m)"
+                    prop:value=radius on:input=move |ev| set_radius.set(event_target_value(&ev))/>
+                <button class="btn btn-xs btn-outline" disabled=move || zone().is_none() on:click=add>"Add"</button>
+            </div>
+        </div>
+    }
+}
+
+/// A file's frequent places that aren't excluded yet, each with a button to exclude it
+#[component]
+pub fn ExclusionSuggestions(filename: String, places: Vec<Place>, zones: RwSignal<Vec<ExclusionZone>>) -> impl IntoView {
+    let suggestions = store_value(places.iter().map(ExclusionZone::from).collect::<Vec<_>>());
+    let pending = move || {
+        let zones = zones.get();
+        suggestions.get_value().into_iter()
+            .filter(|suggestion| !zones.iter().any(|zone| zone.latitude == suggestion.latitude && zone.longitude == suggestion.longitude))
+            .collect::<Vec<_>>()
+    };
+
+    view! {
+        <Show when=move || !pending().is_empty()>
+            <div class="flex flex-wrap items-center gap-2 mb-2 text-sm">
+                <span>{format!("Frequent places in {}:", filename)}</span>
+                {move || pending().into_iter().map(|suggestion| {
+                    let text = format!("{} ({:.4}, {:.4})", suggestion.label, suggestion.latitude, suggestion.longitude);
+                    view! {
+                        <button class="btn btn-xs btn-outline" title="Exclude this place"
+                            on:click=move |_| zones.update(|zones| zones.push(suggestion.clone()))>{text}</button>
+                    }
+                }).collect_view()}
+            </div>
+        </Show>
+    }
+}
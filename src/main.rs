@@ -1,8 +1,11 @@
 mod app;
-mod model;
-mod compute;
+mod exclusions;
+mod map;
+mod sessions;
+mod table;
+mod timeline;
 mod utils;
-mod decoders;
+mod workers;
 mod errors;
 
 use app::*;
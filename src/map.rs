@@ -0,0 +1,136 @@
+use leptos::*;
+use chance_encounters_core::model::SpaceTimePoint;
+use crate::app::EncounterView;
+
+const MAP_WIDTH: f64 = 800.0;
+const MAP_HEIGHT: f64 = 500.0;
+const MAP_PADDING: f64 = 40.0;
+const MIN_SPAN_METERS: f64 = 200.0; // keeps a single stationary point from zooming in forever
+const EARTH_RADIUS_METERS: f64 = 6371000.0;
+
+/// Web mercator projection fitted to a set of points, drawn as plain SVG so it works offline
+struct Projection {
+    min_x: f64,
+    max_y: f64,
+    scale: f64,
+    offset_x: f64,
+    offset_y: f64,
+    meters_per_pixel: f64,
+}
+
+impl Projection {
+    fn mercator(latitude: f64, longitude: f64) -> (f64, f64) {
+        let x = longitude.to_radians();
+        let y = (std::f64::consts::FRAC_PI_4 + latitude.to_radians() / 2.0).tan().ln();
+        (x, y)
+    }
+
+    fn fit(coordinates: &[(f64, f64)]) -> Self {
+        let projected: Vec<(f64, f64)> = coordinates.iter().map(|(latitude, longitude)| Self::mercator(*latitude, *longitude)).collect();
+        let (mut min_x, mut max_x, mut min_y, mut max_y) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+        for (x, y) in &projected {
+            min_x = min_x.min(*x);
+            max_x = max_x.max(*x);
+            min_y = min_y.min(*y);
+            max_y = max_y.max(*y);
+        }
+
+        // Mercator stretches distances by 1/cos(latitude), so the minimum span does too
+        let center_latitude = coordinates.iter().map(|(latitude, _)| latitude).sum::<f64>() / coordinates.len().max(1) as f64;
+        let stretch = 1.0 / center_latitude.to_radians().cos().max(0.01);
+        let min_span = MIN_SPAN_METERS / EARTH_RADIUS_METERS * stretch;
+        let (span_x, span_y) = ((max_x - min_x).max(min_span), (max_y - min_y).max(min_span));
+
+        let scale = ((MAP_WIDTH - 2.0 * MAP_PADDING) / span_x).min((MAP_HEIGHT - 2.0 * MAP_PADDING) / span_y);
+        // Center the drawing in whichever dimension has room to spare
+        let offset_x = (MAP_WIDTH - (max_x - min_x) * scale) / 2.0;
+        let offset_y = (MAP_HEIGHT - (max_y - min_y) * scale) / 2.0;
+        let meters_per_pixel = EARTH_RADIUS_METERS / stretch / scale;
+
+        Projection { min_x, max_y, scale, offset_x, offset_y, meters_per_pixel }
+    }
+
+    fn project(&self, latitude: f64, longitude: f64) -> (f64, f64) {
+        let (x, y) = Self::mercator(latitude, longitude);
+        (self.offset_x + (x - self.min_x) * self.scale, self.offset_y + (self.max_y - y) * self.scale)
+    }
+
+    /// A round scale bar length no longer than `max_pixels`
+    fn scale_bar(&self, max_pixels: f64) -> (f64, String) {
+        let max_meters = max_pixels * self.meters_per_pixel;
+        let magnitude = 10f64.powf(max_meters.log10().floor());
+        let meters = [5.0, 2.0, 1.0].iter().map(|step| step * magnitude).find(|meters| *meters <= max_meters).unwrap_or(magnitude);
+        let label = if meters >= 1000.0 { format!("{} km", meters / 1000.0) } else { format!("{} m", meters) };
+        (meters / self.meters_per_pixel, label)
+    }
+
+    fn polyline(&self, track: &[SpaceTimePoint]) -> String {
+        track.iter()
+            .map(|point| self.project(point.latitude, point.longitude))
+            .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[component]
+pub fn EncounterMap(encounters: Vec<EncounterView>, selected: RwSignal<usize>) -> impl IntoView {
+    let encounter_count = encounters.len();
+    let encounters = store_value(encounters);
+
+    let previous = move |_| selected.update(|index| *index = index.saturating_sub(1));
+    let next = move |_| selected.update(|index| *index = (*index + 1).min(encounter_count.saturating_sub(1)));
+
+    view! {
+        <Show when=move || encounter_count != 0 fallback=|| view! { <p class="mb-4">"No encounters found"</p> }>
+            <div class="mb-4 w-full">
+                <div class="flex items-center justify-between mb-2">
+                    <button class="btn btn-sm" on:click=previous disabled={move || selected.get() == 0}>"Previous"</button>
+                    <span>{move || format!("Encounter {} of {}", selected.get() + 1, encounter_count)}</span>
+                    <button class="btn btn-sm" on:click=next disabled={move || selected.get() + 1 >= encounter_count}>"Next"</button>
+                </div>
+                {move || encounters.with_value(|encounters| view! { <EncounterMapView encounter=encounters[selected.get()].clone()/> })}
+            </div>
+        </Show>
+    }
+}
+
+#[component]
+fn EncounterMapView(encounter: EncounterView) -> impl IntoView {
+    let coordinates: Vec<(f64, f64)> = encounter.track1.iter().chain(&encounter.track2)
+        .map(|point| (point.latitude, point.longitude))
+        .chain([(encounter.encounter.latitude, encounter.encounter.longitude)])
+        .collect();
+    let projection = Projection::fit(&coordinates);
+    let (marker_x, marker_y) = projection.project(encounter.encounter.latitude, encounter.encounter.longitude);
+    let (scale_bar_pixels, scale_bar_label) = projection.scale_bar(150.0);
+
+    let track_points = |track: &[SpaceTimePoint], class: &'static str| track.iter().map(|point| {
+        let (x, y) = projection.project(point.latitude, point.longitude);
+        let offset = point.utc_offset();
+        let title = format!("{} - {}", point.start_time.with_timezone(&offset).format("%Y-%m-%d %H:%M:%S %:z"), point.end_time.with_timezone(&offset).format("%H:%M:%S"));
+        view! { <circle cx=x cy=y r="3" class=class><title>{title}</title></circle> }
+    }).collect_view();
+
+    let offset = encounter.encounter.utc_offset();
+
+    view! {
+        <svg viewBox=format!("0 0 {} {}", MAP_WIDTH, MAP_HEIGHT) class="w-full rounded-box bg-base-200">
+            <polyline points=projection.polyline(&encounter.track1) fill="none" stroke-width="2" class="stroke-primary"/>
+            <polyline points=projection.polyline(&encounter.track2) fill="none" stroke-width="2" class="stroke-secondary"/>
+            {track_points(&encounter.track1, "fill-primary")}
+            {track_points(&encounter.track2, "fill-secondary")}
+            <circle cx=marker_x cy=marker_y r="10" fill="none" stroke-width="3" class="stroke-accent"/>
+            <circle cx=marker_x cy=marker_y r="3" class="fill-accent"/>
+            <line x1=MAP_PADDING y1={MAP_HEIGHT - 20.0} x2={MAP_PADDING + scale_bar_pixels} y2={MAP_HEIGHT - 20.0} stroke-width="2" class="stroke-base-content"/>
+            <text x=MAP_PADDING y={MAP_HEIGHT - 26.0} font-size="12" class="fill-base-content">{scale_bar_label}</text>
+        </svg>
+        <div class="flex justify-between text-sm mt-1">
+            <span><span class="text-primary">"● File 1"</span>" "<span class="text-secondary">"● File 2"</span>" "<span class="text-accent">"◎ Closest approach"</span></span>
+            <span>{format!("{} - {}, {:.0}m apart",
+                encounter.encounter.start_time.with_timezone(&offset).format("%Y-%m-%d %H:%M"),
+                encounter.encounter.end_time.with_timezone(&offset).format("%H:%M %:z"),
+                encounter.encounter.min_distance_km * 1000.0)}</span>
+        </div>
+    }
+}
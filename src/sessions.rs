@@ -0,0 +1,55 @@
+use leptos::*;
+use crate::errors::Error;
+use crate::utils::{ErrorMessages, sessions::*};
+
+/// Analyses saved in this browser, newest first, with buttons to open or delete them.
+/// `changed` is bumped whenever a session is saved or deleted so the list is read again
+#[component]
+pub fn RecentSessions(changed: RwSignal<u64>, on_open: Callback<String>) -> impl IntoView {
+    // Deleting finishes outside the component, where the error context can't be looked up
+    let set_error_messages = expect_context::<WriteSignal<ErrorMessages>>();
+    let summaries = create_local_resource(move || changed.get(), |_| async move {
+        list_sessions().await.unwrap_or_else(|err| {
+            logging::warn!("{}", err);
+            Vec::new()
+        })
+    });
+    let delete = move |id: Option<String>| spawn_local(async move {
+        let deleted = match id {
+            Some(id) => delete_session(&id).await,
+            None => delete_all_sessions().await,
+        };
+        match deleted {
+            Ok(()) => changed.update(|changed| *changed += 1),
+            Err(err) => set_error_messages.update(|messages| messages.push(Error::from(err))),
+        }
+    });
+    let format_saved_at = |saved_at: f64| String::from(js_sys::Date::new(&saved_at.into()).to_locale_string("default", &wasm_bindgen::JsValue::UNDEFINED));
+
+    view! {
+        <Show when=move || summaries.with(|summaries| summaries.as_ref().is_some_and(|summaries| !summaries.is_empty()))>
+            <div class="w-full max-w-2xl mb-4">
+                <div class="flex items-center justify-between mb-1">
+                    <h2 class="text-sm font-bold">"Recent sessions"</h2>
+                    <button class="btn btn-xs btn-ghost" on:click=move |_| delete(None)>"Delete all"</button>
+                </div>
+                <ul class="text-sm">
+                    {move || summaries.get().unwrap_or_default().into_iter().map(|summary| {
+                        let (open_id, delete_id) = (summary.id.clone(), summary.id.clone());
+                        view! {
+                            <li class="flex items-center justify-between gap-2">
+                                <span>{format!("{} and {}, {} encounters", summary.filenames[0], summary.filenames[1], summary.encounter_count)}</span>
+                                <span class="text-xs opacity-70">{format_saved_at(summary.saved_at)}</span>
+                                <span class="flex gap-1">
+                                    <button class="btn btn-xs btn-outline" on:click=move |_| on_open.call(open_id.clone())>"Open"</button>
+                                    <button class="btn btn-xs btn-ghost" title="Delete" on:click=move |_| delete(Some(delete_id.clone()))>"✕"</button>
+                                </span>
+                            </li>
+                        }
+                    }).collect_view()}
+                </ul>
+                <p class="text-xs opacity-70 mt-1">"Decoded histories and results are kept in this browser so they can be opened again without the files."</p>
+            </div>
+        </Show>
+    }
+}
@@ -0,0 +1,186 @@
+use leptos::*;
+use chrono::NaiveDate;
+use chance_encounters_core::{compute::Encounter, export::format_duration, model::Activity};
+
+const PAGE_SIZE: usize = 50;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    Time,
+    Duration,
+    Distance,
+    Location,
+    Activity1,
+    Activity2,
+    Score,
+    Place,
+}
+
+impl SortColumn {
+    fn compare(&self, a: &Encounter, b: &Encounter) -> std::cmp::Ordering {
+        // Unknown activities sort after known ones
+        let activity = |activity: Option<Activity>| activity.map(|activity| activity.to_string()).unwrap_or_else(|| "~".to_string());
+        match self {
+            SortColumn::Time => a.start_time.cmp(&b.start_time),
+            SortColumn::Duration => a.duration().cmp(&b.duration()),
+            SortColumn::Distance => a.min_distance_km.total_cmp(&b.min_distance_km),
+            SortColumn::Location => a.latitude.total_cmp(&b.latitude).then(a.longitude.total_cmp(&b.longitude)),
+            SortColumn::Activity1 => activity(a.activity1).cmp(&activity(b.activity1)),
+            SortColumn::Activity2 => activity(a.activity2).cmp(&activity(b.activity2)),
+            SortColumn::Score => a.score.total_cmp(&b.score),
+            // Needs the places, sorted in the table instead
+            SortColumn::Place => std::cmp::Ordering::Equal,
+        }
+    }
+}
+
+/// Which encounters are shown, all optional
+#[derive(Clone, Default, PartialEq)]
+struct EncounterFilter {
+    from_date: Option<NaiveDate>,
+    to_date: Option<NaiveDate>,
+    max_distance_m: Option<f64>,
+    min_duration_minutes: Option<f64>,
+    /// Only encounters at (true) or away from (false) either person's frequent places
+    at_place: Option<bool>,
+}
+
+impl EncounterFilter {
+    fn matches(&self, encounter: &Encounter, place: Option<&String>) -> bool {
+        let date = encounter.start_time.with_timezone(&encounter.utc_offset()).date_naive();
+        self.from_date.is_none_or(|from_date| date >= from_date)
+            && self.to_date.is_none_or(|to_date| date <= to_date)
+            && self.max_distance_m.is_none_or(|max_distance_m| encounter.min_distance_km * 1000.0 <= max_distance_m)
+            && self.min_duration_minutes.is_none_or(|min_duration_minutes| encounter.duration().num_seconds() as f64 >= min_duration_minutes * 60.0)
+            && self.at_place.is_none_or(|at_place| place.is_some() == at_place)
+    }
+}
+
+fn format_activity(activity: Option<Activity>) -> String {
+    activity.map(|activity| activity.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+#[component]
+pub fn EncounterTable(encounters: Vec<Encounter>, places: Vec<Option<String>>, selected: RwSignal<usize>) -> impl IntoView {
+    let encounters = store_value(encounters);
+    let places = store_value(places);
+    let sort = create_rw_signal((SortColumn::Time, true));
+    let filter = create_rw_signal(EncounterFilter::default());
+    let page = create_rw_signal(0usize);
+
+    // Indices into `encounters` so selecting a row selects the same encounter on the map and timeline
+    let rows = create_memo(move |_| {
+        let (column, ascending) = sort.get();
+        let filter = filter.get();
+        encounters.with_value(|encounters| places.with_value(|places| {
+            let mut rows: Vec<usize> = (0..encounters.len()).filter(|index| filter.matches(&encounters[*index], places[*index].as_ref())).collect();
+            rows.sort_by(|a, b| {
+                let ordering = match column {
+                    // Encounters away from frequent places sort last
+                    SortColumn::Place => places[*a].as_deref().unwrap_or("~").cmp(places[*b].as_deref().unwrap_or("~")),
+                    column => column.compare(&encounters[*a], &encounters[*b]),
+                };
+                if ascending { ordering } else { ordering.reverse() }
+            });
+            rows
+        }))
+    });
+    let page_count = move || rows.with(|rows| rows.len().div_ceil(PAGE_SIZE).max(1));
+    create_effect(move |_| {
+        filter.track();
+        page.set(0);
+    });
+
+    let header = move |label: &'static str, column: SortColumn| view! {
+        <th class="cursor-pointer select-none" on:click=move |_| sort.update(|(sorted_column, ascending)| {
+            *ascending = *sorted_column != column || !*ascending;
+            *sorted_column = column;
+        })>
+            {label}
+            {move || match sort.get() {
+                (sorted_column, true) if sorted_column == column => " ▲",
+                (sorted_column, false) if sorted_column == column => " ▼",
+                _ => "",
+            }}
+        </th>
+    };
+
+    let filter_input = move |label: &'static str, input_type: &'static str, update: fn(&mut EncounterFilter, &str)| view! {
+        <label class="form-control w-full max-w-xs">
+            <span class="label-text">{label}</span>
+            <input type=input_type min="0" class="input input-bordered input-sm"
+                on:change=move |ev| filter.update(|filter| update(filter, &event_target_value(&ev)))/>
+        </label>
+    };
+
+    let page_rows = move || {
+        let page = page.get();
+        rows.with(|rows| rows.iter().skip(page * PAGE_SIZE).take(PAGE_SIZE).copied().collect::<Vec<usize>>())
+            .into_iter()
+            .map(|index| encounters.with_value(|encounters| {
+                let encounter = &encounters[index];
+                view! {
+                    <tr class={move || if selected.get() == index { "cursor-pointer bg-base-300" } else { "cursor-pointer hover" }}
+                        on:click=move |_| selected.set(index)>
+                        <td title={encounter.start_time.format("%Y-%m-%d %H:%M UTC").to_string()}>
+                            {encounter.start_time.with_timezone(&encounter.utc_offset()).format("%Y-%m-%d %H:%M %:z").to_string()}
+                        </td>
+                        <td>{format_duration(encounter.duration())}</td>
+                        <td>{format!("{:.0}m", encounter.min_distance_km * 1000.0)}</td>
+                        <td>{format!("{:.5}, {:.5}", encounter.latitude, encounter.longitude)}</td>
+                        <td>{format_activity(encounter.activity1)}</td>
+                        <td>{format_activity(encounter.activity2)}</td>
+                        <td>{format!("{:.0}", encounter.score)}</td>
+                        <td>{places.with_value(|places| places[index].clone()).unwrap_or_else(|| "-".to_string())}</td>
+                    </tr>
+                }
+            }))
+            .collect_view()
+    };
+
+    view! {
+        <div class="mb-4 w-full">
+            <div class="flex flex-wrap gap-2 mb-2">
+                {filter_input("From", "date", |filter, value| filter.from_date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok())}
+                {filter_input("To", "date", |filter, value| filter.to_date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok())}
+                {filter_input("Max distance (m)", "number", |filter, value| filter.max_distance_m = value.parse().ok())}
+                {filter_input("Min duration (minutes)", "number", |filter, value| filter.min_duration_minutes = value.parse().ok())}
+                <label class="form-control w-full max-w-xs">
+                    <span class="label-text">"Place"</span>
+                    <select class="select select-bordered select-sm"
+                        on:change=move |ev| filter.update(|filter| filter.at_place = match event_target_value(&ev).as_str() {
+                            "at" => Some(true),
+                            "away" => Some(false),
+                            _ => None,
+                        })>
+                        <option value="any">"Anywhere"</option>
+                        <option value="at">"At frequent places"</option>
+                        <option value="away">"Away from frequent places"</option>
+                    </select>
+                </label>
+            </div>
+            <div class="overflow-x-auto">
+                <table class="table table-zebra table-sm">
+                    <thead>
+                        <tr>
+                            {header("Time (local)", SortColumn::Time)}
+                            {header("Duration", SortColumn::Duration)}
+                            {header("Distance", SortColumn::Distance)}
+                            {header("Location", SortColumn::Location)}
+                            {header("File 1 activity", SortColumn::Activity1)}
+                            {header("File 2 activity", SortColumn::Activity2)}
+                            {header("Score", SortColumn::Score)}
+                            {header("Place", SortColumn::Place)}
+                        </tr>
+                    </thead>
+                    <tbody>{page_rows}</tbody>
+                </table>
+            </div>
+            <div class="flex items-center justify-between mt-2">
+                <button class="btn btn-sm" on:click=move |_| page.update(|page| *page = page.saturating_sub(1)) disabled={move || page.get() == 0}>"Previous"</button>
+                <span>{move || format!("Page {} of {}, {} of {} encounters", page.get() + 1, page_count(), rows.with(Vec::len), encounters.with_value(Vec::len))}</span>
+                <button class="btn btn-sm" on:click=move |_| page.update(|page| *page += 1) disabled={move || page.get() + 1 >= page_count()}>"Next"</button>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,286 @@
+use leptos::*;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use chance_encounters_core::{compute::Encounter, timeline::{SegmentKind, TimelineSegment}};
+
+const TIMELINE_WIDTH: f64 = 800.0;
+const LABEL_WIDTH: f64 = 60.0;
+const PLOT_WIDTH: f64 = TIMELINE_WIDTH - LABEL_WIDTH;
+const LANE_HEIGHT: f64 = 24.0;
+const LANE_GAP: f64 = 8.0;
+const LANES_HEIGHT: f64 = 2.0 * LANE_HEIGHT + 3.0 * LANE_GAP;
+const TIMELINE_HEIGHT: f64 = LANES_HEIGHT + 24.0; // room for the axis labels
+const MIN_SPAN_SECS: f64 = 10.0 * 60.0;
+const MIN_MAX_SPAN_SECS: f64 = 24.0 * 60.0 * 60.0; // zooming out is always allowed up to a day
+const MAX_TICKS: f64 = 8.0;
+const ZOOM_FACTOR: f64 = 1.5;
+const PAN_FRACTION: f64 = 0.25;
+const DRAG_THRESHOLD_PIXELS: f64 = 3.0;
+const SELECTED_PADDING_SECS: f64 = 60.0 * 60.0;
+
+fn timestamp(time: DateTime<Utc>) -> f64 {
+    time.timestamp_millis() as f64 / 1000.0
+}
+
+/// The slice of time currently shown, in seconds since the epoch
+#[derive(Clone, Copy, PartialEq)]
+struct TimeWindow {
+    start: f64,
+    span: f64,
+}
+
+impl TimeWindow {
+    fn x(&self, time: f64) -> f64 {
+        LABEL_WIDTH + (time - self.start) / self.span * PLOT_WIDTH
+    }
+
+    /// Keep the window zoomed between minutes and the whole history, and overlapping it
+    fn clamp(&mut self, (min, max): (f64, f64)) {
+        self.span = self.span.clamp(MIN_SPAN_SECS, ((max - min) * 1.2).max(MIN_MAX_SPAN_SECS));
+        self.start = self.start.clamp(min - self.span / 2.0, max - self.span / 2.0);
+    }
+
+    /// Zoom keeping the time under `anchor` (a fraction of the plot width) in place
+    fn zoom(&mut self, factor: f64, anchor: f64, bounds: (f64, f64)) {
+        let anchor_time = self.start + anchor * self.span;
+        self.span *= factor;
+        self.start = anchor_time - anchor * self.span;
+        self.clamp(bounds);
+    }
+
+    fn pan(&mut self, fraction: f64, bounds: (f64, f64)) {
+        self.start += fraction * self.span;
+        self.clamp(bounds);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TickStep {
+    Seconds(i64, &'static str),
+    Months(i32, &'static str),
+}
+
+impl TickStep {
+    fn approximate_secs(&self) -> f64 {
+        match self {
+            TickStep::Seconds(secs, _) => *secs as f64,
+            TickStep::Months(months, _) => *months as f64 * 30.0 * 24.0 * 60.0 * 60.0,
+        }
+    }
+}
+
+const TICK_STEPS: [TickStep; 15] = [
+    TickStep::Seconds(60, "%H:%M"),
+    TickStep::Seconds(5 * 60, "%H:%M"),
+    TickStep::Seconds(15 * 60, "%H:%M"),
+    TickStep::Seconds(30 * 60, "%H:%M"),
+    TickStep::Seconds(60 * 60, "%H:%M"),
+    TickStep::Seconds(3 * 60 * 60, "%b %d %H:%M"),
+    TickStep::Seconds(6 * 60 * 60, "%b %d %H:%M"),
+    TickStep::Seconds(12 * 60 * 60, "%b %d %H:%M"),
+    TickStep::Seconds(24 * 60 * 60, "%b %d"),
+    TickStep::Seconds(7 * 24 * 60 * 60, "%b %d"),
+    TickStep::Months(1, "%b %Y"),
+    TickStep::Months(3, "%b %Y"),
+    TickStep::Months(12, "%Y"),
+    TickStep::Months(5 * 12, "%Y"),
+    TickStep::Months(10 * 12, "%Y"),
+];
+
+/// Axis ticks on round times for the window, stepping by calendar months once zoomed out past weeks
+fn axis_ticks(window: &TimeWindow) -> Vec<(f64, String)> {
+    let step = TICK_STEPS.iter()
+        .find(|step| window.span / step.approximate_secs() <= MAX_TICKS)
+        .copied()
+        .unwrap_or(TICK_STEPS[TICK_STEPS.len() - 1]);
+    let end = window.start + window.span;
+    let mut ticks = Vec::new();
+    match step {
+        TickStep::Seconds(secs, format) => {
+            let mut tick = (window.start as i64).div_euclid(secs) * secs + secs;
+            while (tick as f64) < end {
+                if let Some(time) = DateTime::from_timestamp(tick, 0) {
+                    ticks.push((tick as f64, time.format(format).to_string()));
+                }
+                tick += secs;
+            }
+        },
+        TickStep::Months(months, format) => {
+            let Some(start) = DateTime::from_timestamp(window.start as i64, 0) else { return ticks };
+            let mut month = (start.year() * 12 + start.month0() as i32).div_euclid(months) * months + months;
+            while let Some(time) = Utc.with_ymd_and_hms(month.div_euclid(12), month.rem_euclid(12) as u32 + 1, 1, 0, 0, 0).single() {
+                if timestamp(time) >= end {
+                    break;
+                }
+                ticks.push((timestamp(time), time.format(format).to_string()));
+                month += months;
+            }
+        },
+    }
+    ticks
+}
+
+/// Pixel ranges of the segments inside the window. Neighbours less than a pixel apart are merged so
+/// that a history spanning years is drawn with a bar per pixel rather than a bar per point
+fn visible_bars(segments: &[TimelineSegment], window: &TimeWindow) -> Vec<(f64, f64, SegmentKind)> {
+    let end = window.start + window.span;
+    let first = segments.partition_point(|segment| timestamp(segment.end_time) < window.start);
+    let mut bars: Vec<(f64, f64, SegmentKind)> = Vec::new();
+    for segment in segments[first..].iter().take_while(|segment| timestamp(segment.start_time) <= end) {
+        let x1 = window.x(timestamp(segment.start_time)).max(LABEL_WIDTH);
+        let x2 = window.x(timestamp(segment.end_time)).min(TIMELINE_WIDTH);
+        match bars.last_mut() {
+            Some(bar) if x1 - bar.1 < 1.0 && (bar.2 == segment.kind || x2 - x1 < 1.0) => bar.1 = bar.1.max(x2),
+            _ => bars.push((x1, x2, segment.kind)),
+        }
+    }
+    bars
+}
+
+#[component]
+pub fn Timeline(timeline1: Vec<TimelineSegment>, timeline2: Vec<TimelineSegment>, encounters: Vec<Encounter>, selected: RwSignal<usize>) -> impl IntoView {
+    let bounds = {
+        let times = || timeline1.iter().chain(&timeline2).flat_map(|segment| [timestamp(segment.start_time), timestamp(segment.end_time)]);
+        (times().fold(f64::MAX, f64::min), times().fold(f64::MIN, f64::max))
+    };
+    let has_history = bounds.0 <= bounds.1;
+    let whole_history = move || {
+        let mut window = TimeWindow { start: bounds.0, span: bounds.1 - bounds.0 };
+        window.clamp(bounds);
+        window.start = (bounds.0 + bounds.1 - window.span) / 2.0;
+        window
+    };
+    let window = create_rw_signal(whole_history());
+
+    let timeline1 = store_value(timeline1);
+    let timeline2 = store_value(timeline2);
+    let encounters = store_value(encounters);
+
+    // Mouse positions come in CSS pixels, which only match the SVG's units when it isn't scaled
+    let svg_ref = create_node_ref::<svg::Svg>();
+    let to_svg_x = move |client_x: i32| svg_ref.get_untracked()
+        .map(|svg| {
+            let rect = svg.get_bounding_client_rect();
+            (client_x as f64 - rect.left()) * TIMELINE_WIDTH / rect.width().max(1.0)
+        })
+        .unwrap_or_default();
+    let plot_fraction = move |client_x: i32| ((to_svg_x(client_x) - LABEL_WIDTH) / PLOT_WIDTH).clamp(0.0, 1.0);
+
+    let on_wheel = move |ev: ev::WheelEvent| {
+        ev.prevent_default();
+        let factor = if ev.delta_y() > 0.0 { ZOOM_FACTOR } else { 1.0 / ZOOM_FACTOR };
+        window.update(|window| window.zoom(factor, plot_fraction(ev.client_x()), bounds));
+    };
+
+    // Dragging pans, but a drag shouldn't also count as a click on the encounter it started on
+    let drag = store_value(None::<(f64, f64)>);
+    let dragged = store_value(false);
+    let on_mousedown = move |ev: ev::MouseEvent| {
+        drag.set_value(Some((to_svg_x(ev.client_x()), window.get_untracked().start)));
+        dragged.set_value(false);
+    };
+    let on_mousemove = move |ev: ev::MouseEvent| {
+        let Some((origin_x, origin_start)) = drag.get_value() else { return };
+        let pixels = to_svg_x(ev.client_x()) - origin_x;
+        if pixels.abs() > DRAG_THRESHOLD_PIXELS {
+            dragged.set_value(true);
+        }
+        if dragged.get_value() {
+            window.update(|window| {
+                window.start = origin_start - pixels / PLOT_WIDTH * window.span;
+                window.clamp(bounds);
+            });
+        }
+    };
+    let end_drag = move |_: ev::MouseEvent| drag.set_value(None);
+
+    let show_selected = move |_| encounters.with_value(|encounters| {
+        let Some(encounter) = encounters.get(selected.get_untracked()) else { return };
+        let mut selected_window = TimeWindow {
+            start: timestamp(encounter.start_time) - SELECTED_PADDING_SECS,
+            span: timestamp(encounter.end_time) - timestamp(encounter.start_time) + 2.0 * SELECTED_PADDING_SECS,
+        };
+        selected_window.clamp(bounds);
+        window.set(selected_window);
+    });
+
+    let lane = move |segments: StoredValue<Vec<TimelineSegment>>, y: f64, class: &'static str| move || {
+        let window = window.get();
+        segments.with_value(|segments| visible_bars(segments, &window)).into_iter().map(|(x1, x2, kind)| {
+            let (opacity, title) = match kind {
+                SegmentKind::Visit => ("1", "Visit"),
+                SegmentKind::Movement => ("0.4", "Moving"),
+            };
+            view! { <rect x=x1 y=y width={(x2 - x1).max(1.0)} height=LANE_HEIGHT opacity=opacity class=class><title>{title}</title></rect> }
+        }).collect_view()
+    };
+
+    let encounter_bands = move || {
+        let window = window.get();
+        encounters.with_value(|encounters| encounters.iter().enumerate()
+            .filter(|(_, encounter)| timestamp(encounter.end_time) >= window.start && timestamp(encounter.start_time) <= window.start + window.span)
+            .map(|(index, encounter)| {
+                let x1 = window.x(timestamp(encounter.start_time)).max(LABEL_WIDTH);
+                let x2 = window.x(timestamp(encounter.end_time)).min(TIMELINE_WIDTH);
+                let offset = encounter.utc_offset();
+                let title = format!("{} - {}, {:.0}m apart", encounter.start_time.with_timezone(&offset).format("%Y-%m-%d %H:%M"),
+                    encounter.end_time.with_timezone(&offset).format("%H:%M %:z"), encounter.min_distance_km * 1000.0);
+                let select = move |_| if !dragged.get_value() { selected.set(index) };
+                view! {
+                    <rect x={x1 - 1.0} y="0" width={(x2 - x1).max(0.0) + 2.0} height=LANES_HEIGHT class="fill-accent stroke-accent cursor-pointer"
+                        fill-opacity={move || if selected.get() == index { "0.6" } else { "0.25" }}
+                        stroke-width={move || if selected.get() == index { "2" } else { "0" }}
+                        on:click=select>
+                        <title>{title}</title>
+                    </rect>
+                }
+            })
+            .collect_view())
+    };
+
+    let ticks = move || {
+        let window = window.get();
+        axis_ticks(&window).into_iter().map(|(time, label)| {
+            let x = window.x(time);
+            view! {
+                <line x1=x y1="0" x2=x y2=LANES_HEIGHT stroke-width="1" stroke-opacity="0.2" class="stroke-base-content"/>
+                <text x=x y={LANES_HEIGHT + 16.0} font-size="11" text-anchor="middle" class="fill-base-content">{label}</text>
+            }
+        }).collect_view()
+    };
+
+    let button = move |label: &'static str, action: fn(&mut TimeWindow, (f64, f64))| view! {
+        <button class="btn btn-sm" on:click=move |_| window.update(|window| action(window, bounds))>{label}</button>
+    };
+
+    view! {
+        <Show when=move || has_history>
+            <div class="mb-4 w-full">
+                <div class="flex items-center justify-between mb-2">
+                    <div class="join">
+                        {button("◀", |window, bounds| window.pan(-PAN_FRACTION, bounds))}
+                        {button("▶", |window, bounds| window.pan(PAN_FRACTION, bounds))}
+                    </div>
+                    <div class="join">
+                        {button("-", |window, bounds| window.zoom(ZOOM_FACTOR, 0.5, bounds))}
+                        {button("+", |window, bounds| window.zoom(1.0 / ZOOM_FACTOR, 0.5, bounds))}
+                        <button class="btn btn-sm" on:click=move |_| window.set(whole_history())>"All"</button>
+                        <button class="btn btn-sm" on:click=show_selected disabled={move || encounters.with_value(|encounters| encounters.is_empty())}>"Selected"</button>
+                    </div>
+                </div>
+                <svg viewBox=format!("0 0 {} {}", TIMELINE_WIDTH, TIMELINE_HEIGHT) class="w-full rounded-box bg-base-200 select-none cursor-grab" node_ref=svg_ref
+                    on:wheel=on_wheel on:mousedown=on_mousedown on:mousemove=on_mousemove on:mouseup=end_drag on:mouseleave=end_drag>
+                    {ticks}
+                    <text x="8" y={LANE_GAP + LANE_HEIGHT / 2.0 + 4.0} font-size="12" class="fill-primary">"File 1"</text>
+                    <text x="8" y={2.0 * LANE_GAP + 1.5 * LANE_HEIGHT + 4.0} font-size="12" class="fill-secondary">"File 2"</text>
+                    {lane(timeline1, LANE_GAP, "fill-primary")}
+                    {lane(timeline2, 2.0 * LANE_GAP + LANE_HEIGHT, "fill-secondary")}
+                    {encounter_bands}
+                </svg>
+                <div class="flex justify-between text-sm mt-1">
+                    <span>"Solid: visit, faded: moving, "<span class="text-accent">"■ encounter"</span></span>
+                    <span>"Scroll to zoom, drag to pan"</span>
+                </div>
+            </div>
+        </Show>
+    }
+}
@@ -8,6 +8,10 @@ pub enum FileProcessingError {
     MissingFileError,
     FileReaderError(String),
     InProcessError,
+    DownloadError(String),
+    StorageError(String),
+    /// A file from a restored session has to be chosen again to decode it differently
+    SessionFileMissing(String),
 }
 
 impl FileProcessingError {
@@ -26,6 +30,9 @@ impl fmt::Display for FileProcessingError {
             FileProcessingError::MissingFileError => write!(f, "Please provide both files"),
             FileProcessingError::FileReaderError(msg) => write!(f, "{}", msg),
             FileProcessingError::InProcessError => write!(f, "File is still being processed"),
+            FileProcessingError::DownloadError(msg) => write!(f, "Unable to download {}", msg),
+            FileProcessingError::StorageError(msg) => write!(f, "Unable to use saved sessions: {}", msg),
+            FileProcessingError::SessionFileMissing(filename) => write!(f, "{} is from a saved session, choose it again to change the date or area", filename),
         }
     }
 }
\ No newline at end of file
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
-use web_sys::{File, FileReader};
+use web_sys::{Blob, BlobPropertyBag, File, FileReader, HtmlAnchorElement, Url};
+use js_sys::{ArrayBuffer, Uint8Array};
 use super::errors::FileProcessingError;
 use wasm_bindgen::prelude::*;
 use leptos::*;
+use chance_encounters_core::cache::content_hash;
 
 /// Get filename from path
 pub fn get_filename(path: &str) -> Result<String, FileProcessingError> {
@@ -37,7 +39,9 @@ pub fn get_file_info(file_ref: &NodeRef<html::Input>) -> Result<FileInfo, FilePr
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct FileContent {
     pub filename: String,
-    pub content: String
+    /// Hash of the content, so the worker can reuse a file it already decoded
+    pub hash: u64,
+    pub content: Vec<u8>
 }
 pub type FileContents = Option<(FileContent, FileContent)>;
 pub type FileResult = Result<FileContent, FileProcessingError>;
@@ -54,17 +58,18 @@ pub fn process_file(file_info: FileInfo, set_file_out: WriteSignal<FileResult>)
         match file_reader_clone.ready_state() {
             FileReader::DONE => match file_reader_clone.result() {
                 Ok(js_value) => {
-                    match js_value.as_string() {
-                        Some(value_as_string) => {
-                            match value_as_string.is_empty() {
+                    match js_value.dyn_into::<ArrayBuffer>() {
+                        Ok(array_buffer) => {
+                            let content = Uint8Array::new(&array_buffer).to_vec();
+                            match content.is_empty() {
                                 true => set_file_out.set(Err(FileProcessingError::FileReaderError(format!("{}: is empty file", filename)))),
-                                false => set_file_out.set(Ok(FileContent { filename: filename.clone(), content: value_as_string })) // Clone filename as it has been moved here but we'll need to refer to it later
+                                false => set_file_out.set(Ok(FileContent { filename: filename.clone(), hash: content_hash(&content), content })) // Clone filename as it has been moved here but we'll need to refer to it later
                             }
                         }
-                        None => set_file_out.set(Err(FileProcessingError::FileReaderError(format!("{}: can not be parsed as string", filename))))
+                        Err(_) => set_file_out.set(Err(FileProcessingError::FileReaderError(format!("{}: can not be read as bytes", filename))))
                     }
                 }
-                Err(_) => set_file_out.set(Err(FileProcessingError::FileReaderError(format!("{}: Filereader unable to read as text", filename))))
+                Err(_) => set_file_out.set(Err(FileProcessingError::FileReaderError(format!("{}: Filereader unable to read file", filename))))
             }
             _ => set_file_out.set(Err(FileProcessingError::FileReaderError(
                                 format!("{}: Filereader State returned {}",
@@ -73,11 +78,27 @@ pub fn process_file(file_info: FileInfo, set_file_out: WriteSignal<FileResult>)
         }
     }) as Box<dyn Fn()>);
     file_reader.set_onloadend(Some(onloadend_callback.as_ref().unchecked_ref()));
-    let _ = file_reader.read_as_text(&file);
+    let _ = file_reader.read_as_array_buffer(&file);
     onloadend_callback.forget();
     Ok(())
 }
 
+/// Save generated content by clicking a temporary link to a Blob of it
+pub fn download_file(filename: &str, mime_type: &str, content: &[u8]) -> Result<(), FileProcessingError> {
+    let download_error = |err: JsValue| FileProcessingError::DownloadError(format!("{}: {:#?}", filename, err));
+    let parts = js_sys::Array::of1(&Uint8Array::from(content));
+    let mut options = BlobPropertyBag::new();
+    options.type_(mime_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options).map_err(download_error)?;
+    let url = Url::create_object_url_with_blob(&blob).map_err(download_error)?;
+
+    let anchor = document().create_element("a").map_err(download_error)?.unchecked_into::<HtmlAnchorElement>();
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    anchor.click();
+    Url::revoke_object_url(&url).map_err(download_error)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,5 +1,7 @@
 pub mod fileutils;
 pub mod errors;
+pub mod storage;
+pub mod sessions;
 
 use leptos::*;
 use shrinkwraprs::Shrinkwrap;
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbRequest, IdbTransaction, IdbTransactionMode, Window, WorkerGlobalScope};
+use js_sys::{Array, Uint8Array};
+use leptos::*;
+use chance_encounters_core::{compute::EncounterOptions, exclusion::ExclusionZone, filter::PointFilter, model::SpaceTimeRecord};
+use crate::{app::AnalysisOutput, workers::Preparation};
+use super::errors::FileProcessingError;
+
+const DATABASE_NAME: &str = "chance-encounters";
+/// Sessions are bincode, which can't read an older layout, so each version that changes them starts the stores afresh
+const DATABASE_VERSION: u32 = 3;
+/// Summaries are kept apart from the sessions so listing them doesn't read every stored record
+const SUMMARIES_STORE: &str = "summaries";
+const SESSIONS_STORE: &str = "sessions";
+/// Older sessions are deleted when more than this many are saved, decoded records can be large
+pub const MAX_SESSIONS: usize = 10;
+
+/// What's shown in the recent sessions list
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub id: String,
+    /// Milliseconds since the epoch
+    pub saved_at: f64,
+    pub filenames: [String; 2],
+    pub point_counts: [usize; 2],
+    pub encounter_count: usize,
+}
+
+/// A decoded file, stored instead of its content
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub filename: String,
+    pub hash: u64,
+    pub record: SpaceTimeRecord,
+    /// Name of the decoder the file was read with, or the one a saved record or bundle named
+    pub source_format: String,
+}
+
+/// Everything needed to show an analysis again and rerun it with other thresholds without the original files
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub summary: SessionSummary,
+    pub files: [StoredFile; 2],
+    pub options: EncounterOptions,
+    pub filter: PointFilter,
+    pub zones: [Vec<ExclusionZone>; 2],
+    pub preparation: Preparation,
+    pub output: AnalysisOutput,
+}
+
+fn storage_error(err: impl std::fmt::Debug) -> FileProcessingError {
+    FileProcessingError::StorageError(format!("{:?}", err))
+}
+
+/// Wait for an IndexedDB request to finish and take its result
+async fn request_result(request: &IdbRequest) -> Result<JsValue, FileProcessingError> {
+    let promise = js_sys::Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(promise).await.map_err(|_| storage_error(request.error()))?;
+    request.result().map_err(storage_error)
+}
+
+/// Requests in a transaction are all made before waiting on any, so the transaction can't finish in between
+async fn wait_for_all(requests: Vec<IdbRequest>) -> Result<(), FileProcessingError> {
+    for request in requests {
+        request_result(&request).await?;
+    }
+    Ok(())
+}
+
+/// IndexedDB from the page, or from the worker that saves sessions as it keeps the records
+fn idb_factory() -> Result<IdbFactory, FileProcessingError> {
+    let global = js_sys::global();
+    let factory = match global.dyn_ref::<Window>() {
+        Some(window) => window.indexed_db(),
+        None => global.unchecked_into::<WorkerGlobalScope>().indexed_db(),
+    };
+    factory.map_err(storage_error)?.ok_or_else(|| FileProcessingError::StorageError("IndexedDB isn't available".to_string()))
+}
+
+async fn open_database() -> Result<IdbDatabase, FileProcessingError> {
+    let factory = idb_factory()?;
+    let request = factory.open_with_u32(DATABASE_NAME, DATABASE_VERSION).map_err(storage_error)?;
+    let upgrade_request = request.clone();
+    let on_upgrade_needed: Closure<dyn Fn()> = Closure::wrap(Box::new(move || {
+        let Ok(database) = upgrade_request.result().map(|database| database.unchecked_into::<IdbDatabase>()) else { return };
+        for store in [SUMMARIES_STORE, SESSIONS_STORE] {
+            if database.object_store_names().contains(store) {
+                let _ = database.delete_object_store(store);
+            }
+            let _ = database.create_object_store(store);
+        }
+    }) as Box<dyn Fn()>);
+    request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+    let database = request_result(&request).await?;
+    request.set_onupgradeneeded(None);
+    Ok(database.unchecked_into())
+}
+
+/// A transaction that can change both stores, so a summary is never kept without its session
+fn write_transaction(database: &IdbDatabase) -> Result<IdbTransaction, FileProcessingError> {
+    let stores = Array::of2(&JsValue::from_str(SUMMARIES_STORE), &JsValue::from_str(SESSIONS_STORE));
+    database.transaction_with_str_sequence_and_mode(&stores, IdbTransactionMode::Readwrite).map_err(storage_error)
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Uint8Array, FileProcessingError> {
+    let bytes = bincode::serialize(value).map_err(storage_error)?;
+    Ok(Uint8Array::from(bytes.as_slice()))
+}
+
+fn decode<T: for<'de> Deserialize<'de>>(value: JsValue) -> Result<T, FileProcessingError> {
+    let bytes = value.dyn_into::<Uint8Array>().map_err(storage_error)?.to_vec();
+    bincode::deserialize(&bytes).map_err(storage_error)
+}
+
+/// The saved sessions, most recent first
+pub async fn list_sessions() -> Result<Vec<SessionSummary>, FileProcessingError> {
+    let database = open_database().await?;
+    let store = database.transaction_with_str(SUMMARIES_STORE).and_then(|transaction| transaction.object_store(SUMMARIES_STORE)).map_err(storage_error)?;
+    let values = request_result(&store.get_all().map_err(storage_error)?).await?;
+    let mut summaries = Array::from(&values).iter()
+        .map(decode::<SessionSummary>)
+        .collect::<Result<Vec<_>, _>>()?;
+    summaries.sort_by(|a, b| b.saved_at.total_cmp(&a.saved_at));
+    Ok(summaries)
+}
+
+pub async fn load_session(id: &str) -> Result<Session, FileProcessingError> {
+    let database = open_database().await?;
+    let store = database.transaction_with_str(SESSIONS_STORE).and_then(|transaction| transaction.object_store(SESSIONS_STORE)).map_err(storage_error)?;
+    let value = request_result(&store.get(&JsValue::from_str(id)).map_err(storage_error)?).await?;
+    if value.is_undefined() {
+        return Err(FileProcessingError::StorageError(format!("Session {} no longer exists", id)));
+    }
+    decode(value)
+}
+
+/// Save a session, replacing any with the same id, and delete the oldest ones past `MAX_SESSIONS`
+pub async fn save_session(session: &Session) -> Result<(), FileProcessingError> {
+    let (summary, data) = (encode(&session.summary)?, encode(session)?);
+    let outdated: Vec<String> = list_sessions().await?.into_iter()
+        .filter(|summary| summary.id != session.summary.id)
+        .skip(MAX_SESSIONS - 1)
+        .map(|summary| summary.id)
+        .collect();
+    for id in outdated {
+        delete_session(&id).await?;
+    }
+
+    let database = open_database().await?;
+    let transaction = write_transaction(&database)?;
+    let key = JsValue::from_str(&session.summary.id);
+    let requests = [(SUMMARIES_STORE, summary), (SESSIONS_STORE, data)].into_iter()
+        .map(|(store, value)| transaction.object_store(store).and_then(|store| store.put_with_key(&value, &key)).map_err(storage_error))
+        .collect::<Result<Vec<_>, _>>()?;
+    wait_for_all(requests).await
+}
+
+pub async fn delete_session(id: &str) -> Result<(), FileProcessingError> {
+    let database = open_database().await?;
+    let transaction = write_transaction(&database)?;
+    let key = JsValue::from_str(id);
+    let requests = [SUMMARIES_STORE, SESSIONS_STORE].into_iter()
+        .map(|store| transaction.object_store(store).and_then(|store| store.delete(&key)).map_err(storage_error))
+        .collect::<Result<Vec<_>, _>>()?;
+    wait_for_all(requests).await
+}
+
+/// Delete every saved session, for when someone doesn't want their history kept in the browser
+pub async fn delete_all_sessions() -> Result<(), FileProcessingError> {
+    let database = open_database().await?;
+    let transaction = write_transaction(&database)?;
+    let requests = [SUMMARIES_STORE, SESSIONS_STORE].into_iter()
+        .map(|store| transaction.object_store(store).and_then(|store| store.clear()).map_err(storage_error))
+        .collect::<Result<Vec<_>, _>>()?;
+    wait_for_all(requests).await
+}
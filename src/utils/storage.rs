@@ -0,0 +1,25 @@
+use serde::{de::DeserializeOwned, Serialize};
+use leptos::*;
+
+/// Read a value saved with `save`, None if there isn't one or local storage isn't available
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let storage = window().local_storage().ok()??;
+    let value = storage.get_item(key).ok()??;
+    match serde_json::from_str(&value) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            logging::warn!("Ignoring saved {}: {}", key, err);
+            None
+        }
+    }
+}
+
+/// Save a value to local storage as JSON, a failure only means it's forgotten on the next visit
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    let saved = serde_json::to_string(value).ok()
+        .zip(window().local_storage().ok().flatten())
+        .map(|(value, storage)| storage.set_item(key, &value));
+    if !matches!(saved, Some(Ok(()))) {
+        logging::warn!("Unable to save {} to local storage", key);
+    }
+}
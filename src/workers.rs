@@ -0,0 +1,417 @@
+//! Workers that keep the decoded records between analyses, so rerunning with other settings only sends them the
+//! settings. Every worker keeps both records and matches its own time range of the first against all of the second,
+//! and the first worker puts together what's shown afterwards
+
+use std::{cell::{Cell, RefCell}, collections::HashMap, rc::Rc, sync::Arc};
+use futures::{channel::mpsc, StreamExt};
+use leptos::*;
+use leptos_workers::{worker, executors::{AbortHandle, PoolExecutor}, CreateWorkerError, Sender};
+use serde::{Deserialize, Serialize};
+use chance_encounters_core::{cache::*, cleaning::{without_outliers, SpeedLimits}, compute::*, decoders::{binary::BinaryRecord, bundle::Bundle, *}, exclusion::*, filter::PointFilter, model::*, places::*, simplify::{simplify, SimplifyOptions}, timeline::*};
+use crate::app::{AnalysisOutput, EncounterView};
+use crate::errors::Error;
+use crate::utils::sessions::*;
+
+/// Upper limit on workers regardless of how many cores the browser reports
+const MAX_WORKERS: usize = 8;
+/// How many decoded files are kept, enough to swap either file and come back
+const RECORD_CACHE_CAPACITY: usize = 4;
+/// How many records are kept prepared, or without their exclusion zones and indexed, enough for both sides of one analysis
+const MATCHED_CACHE_CAPACITY: usize = 2;
+/// How many of each person's frequent places are found, to label encounters and offer as exclusion zones
+const MAX_FREQUENT_PLACES: usize = 5;
+
+/// What's done to both records before analyzing them, like `--outliers drop` and `--simplify` on the command line. Changing it only
+/// reruns the analysis, the decoded records are kept as they were
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Preparation {
+    /// Drop points that would mean moving impossibly fast, so they can't make encounters
+    pub drop_outliers: bool,
+    /// Thin out points to within this many meters of the path, collapsing stays into one point, after dropping outliers
+    pub simplify_m: Option<f64>,
+}
+
+/// A file for a worker to decode
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileRequest {
+    pub filename: String,
+    pub content: Vec<u8>,
+    pub filter: PointFilter,
+    /// For opening encrypted bundles, empty when there isn't one
+    pub passphrase: String,
+}
+
+/// An analysis to save as a session, the worker adds the records
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveRequest {
+    pub id: String,
+    pub keys: [u64; 2],
+    pub filenames: [String; 2],
+    pub hashes: [u64; 2],
+    pub options: EncounterOptions,
+    pub filter: PointFilter,
+    pub zones: [Vec<ExclusionZone>; 2],
+    pub preparation: Preparation,
+    pub output: AnalysisOutput,
+}
+
+/// Records are referred to by the key they were decoded with, `PointFilter::cache_key` of the file's hash
+#[derive(Clone, Serialize, Deserialize)]
+pub enum AnalysisRequest {
+    /// Decode a file and keep its record, sending it back for the other workers
+    Decode { key: u64, file: FileRequest },
+    /// Keep a record decoded by another worker or restored from a saved session
+    Keep { key: u64, record: SpaceTimeRecord, source_format: String },
+    /// Drop a record the page no longer keeps
+    Forget { key: u64 },
+    /// Send a record back, for sealing it in a bundle
+    Fetch { key: u64 },
+    /// Match one of `shard_count` time ranges of the first record's points against the second record
+    Match { keys: [u64; 2], preparation: Preparation, zones: [Vec<ExclusionZone>; 2], options: EncounterOptions, shard: usize, shard_count: usize },
+    /// Merge the encounters every shard found and put together everything shown about them
+    Summarize { keys: [u64; 2], preparation: Preparation, zones: [Vec<ExclusionZone>; 2], options: EncounterOptions, encounters: Vec<Encounter>, filenames: [String; 2] },
+    Save(Box<SaveRequest>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum AnalysisReply {
+    /// The record and the name of the format it was first decoded from
+    Decoded(Result<(SpaceTimeRecord, String), Error>),
+    Fetched(Result<(SpaceTimeRecord, String), Error>),
+    /// Sent while matching once the second record is indexed
+    Indexed,
+    /// Sent while matching with how much of the shard is done
+    Matching(f64),
+    Matched(Result<Vec<Encounter>, Error>),
+    Summarized(Result<AnalysisOutput, Error>),
+    Saved(Result<(), Error>),
+}
+
+impl AnalysisReply {
+    /// Whether it's the last reply to its request
+    fn is_last(&self) -> bool {
+        !matches!(self, AnalysisReply::Indexed | AnalysisReply::Matching(_))
+    }
+}
+
+/// Requests and replies carry an id, so replies find their way back when several requests are waiting on a worker
+type TaggedRequest = (u64, AnalysisRequest);
+type TaggedReply = (u64, AnalysisReply);
+
+/// A record after its `Preparation`, and what was done to it
+#[derive(Clone)]
+struct Prepared {
+    record: Arc<CachedRecord>,
+    outliers: usize,
+    /// Points left after simplifying, if it was
+    simplified: Option<usize>,
+}
+
+/// What a worker keeps between requests
+struct ResidentState {
+    /// Every decoded record the page still has
+    records: HashMap<u64, Arc<CachedRecord>>,
+    /// The format each record was first decoded from, which a bundle names when it's sealed
+    source_formats: HashMap<u64, String>,
+    /// Least recently used first
+    prepared: Vec<(u64, Prepared)>,
+    /// The prepared records without their exclusion zones, indexed the first time they're matched against
+    matched: RecordCache,
+}
+
+impl ResidentState {
+    fn record(&self, key: u64) -> Result<&Arc<CachedRecord>, Error> {
+        self.records.get(&key).ok_or_else(|| Error::WebWorkerError(format!("record {:016x} isn't loaded", key)))
+    }
+
+    fn source_format(&self, key: u64) -> String {
+        self.source_formats.get(&key).cloned().unwrap_or_default()
+    }
+
+    fn keep(&mut self, key: u64, record: SpaceTimeRecord, source_format: String) {
+        self.records.insert(key, Arc::new(CachedRecord::new(record)));
+        self.source_formats.insert(key, source_format);
+    }
+
+    fn forget(&mut self, key: u64) {
+        self.records.remove(&key);
+        self.source_formats.remove(&key);
+    }
+
+    fn prepared(&mut self, key: u64, preparation: &Preparation) -> Result<Prepared, Error> {
+        let record = self.record(key)?.clone();
+        if *preparation == Preparation::default() {
+            return Ok(Prepared { record, outliers: 0, simplified: None });
+        }
+        let prepared_key = content_hash(format!("{}{:?}", key, preparation).as_bytes());
+        if let Some(position) = self.prepared.iter().position(|(entry_key, _)| *entry_key == prepared_key) {
+            let entry = self.prepared.remove(position);
+            let prepared = entry.1.clone();
+            self.prepared.push(entry);
+            return Ok(prepared);
+        }
+        let (record, outliers) = match preparation.drop_outliers {
+            true => without_outliers(&record.record, &SpeedLimits::default()),
+            false => (record.record.clone(), Vec::new()),
+        };
+        let (record, simplified) = match preparation.simplify_m {
+            Some(tolerance_m) => {
+                let (record, report) = simplify(&record, &SimplifyOptions { tolerance_m, ..SimplifyOptions::default() });
+                (record, Some(report.points_after))
+            },
+            None => (record, None),
+        };
+        let prepared = Prepared { record: Arc::new(CachedRecord::new(record)), outliers: outliers.len(), simplified };
+        if self.prepared.len() >= MATCHED_CACHE_CAPACITY {
+            self.prepared.remove(0);
+        }
+        self.prepared.push((prepared_key, prepared.clone()));
+        Ok(prepared)
+    }
+
+    fn matched(&mut self, key: u64, preparation: &Preparation, zones: &[ExclusionZone]) -> Result<Arc<CachedRecord>, Error> {
+        let prepared = self.prepared(key, preparation)?;
+        if zones.is_empty() {
+            return Ok(prepared.record);
+        }
+        let matched_key = content_hash(format!("{}{:?}{:?}", key, preparation, zones).as_bytes());
+        if let Some(matched) = self.matched.get(matched_key) {
+            return Ok(matched);
+        }
+        Ok(self.matched.insert(matched_key, without_exclusion_zones(&prepared.record.record, zones)))
+    }
+
+    fn decode(&mut self, key: u64, file: FileRequest) -> Result<(SpaceTimeRecord, String), Error> {
+        logging::log!("Decoding {} in a worker", file.filename);
+        let (record, source_format) = match Bundle::is_bundle(&file.content) && !file.passphrase.is_empty() {
+            // Bundles and saved records name the format they were first decoded from, a bundle's only once it's opened
+            true => {
+                let (header, record) = BinaryRecord::decode_filtered(&Bundle::open(&file.content, &file.passphrase)?, &file.filter)?;
+                (record, header.source_format)
+            },
+            false => {
+                let decoders = DecoderRegistry::default();
+                let decoder = decoders.detect(&file.content)?;
+                let record = decoder.decode_filtered(&file.content, &file.filter)?;
+                let source_format = BinaryRecord::read_header(&file.content).map_or_else(|_| decoder.name().to_string(), |header| header.source_format);
+                (record, source_format)
+            },
+        };
+        self.keep(key, record.clone(), source_format.clone());
+        Ok((record, source_format))
+    }
+
+    fn matched_pair(&mut self, keys: [u64; 2], preparation: &Preparation, zones: &[Vec<ExclusionZone>; 2]) -> Result<[Arc<CachedRecord>; 2], Error> {
+        Ok([self.matched(keys[0], preparation, &zones[0])?, self.matched(keys[1], preparation, &zones[1])?])
+    }
+
+    fn summarize(&mut self, keys: [u64; 2], preparation: &Preparation, zones: &[Vec<ExclusionZone>; 2], options: &EncounterOptions, encounters: Vec<Encounter>, filenames: [String; 2]) -> Result<AnalysisOutput, Error> {
+        let [matched1, matched2] = self.matched_pair(keys, preparation, zones)?;
+        let (prepared1, prepared2) = (self.prepared(keys[0], preparation)?, self.prepared(keys[1], preparation)?);
+        let describe = |decoded: &SpaceTimeRecord, prepared: &Prepared, matched: &SpaceTimeRecord| {
+            let mut details = vec![format!("{} warnings", decoded.warnings.len())];
+            if preparation.drop_outliers {
+                details.push(format!("{} outliers removed", prepared.outliers));
+            }
+            if let Some(points) = prepared.simplified {
+                details.push(format!("simplified to {}", points));
+            }
+            details.push(format!("{} excluded", prepared.record.record.points.len() - matched.points.len()));
+            format!("{} points ({})", decoded.points.len(), details.join(", "))
+        };
+        let encounters = merge_encounters(encounters, options);
+        let summary = format!("File 1: {}, File 2: {}, {} encounters",
+            describe(&self.record(keys[0])?.record, &prepared1, &matched1.record), describe(&self.record(keys[1])?.record, &prepared2, &matched2.record), encounters.len());
+        // Only matching leaves out the excluded places, the timeline and tracks still show everything else
+        let (record1, record2) = (&prepared1.record.record, &prepared2.record.record);
+        let (places1, places2) = (frequent_places(record1, MAX_FREQUENT_PLACES), frequent_places(record2, MAX_FREQUENT_PLACES));
+        let encounters = encounters.into_iter().map(|encounter| EncounterView::new(encounter, record1, record2, &places1, &places2)).collect();
+        let max_gap = chrono::Duration::minutes(DEFAULT_MAX_GAP_MINUTES);
+        let (timeline1, timeline2) = (timeline_segments(record1, max_gap), timeline_segments(record2, max_gap));
+        let [filename1, filename2] = filenames;
+        Ok(AnalysisOutput { summary, filename1, filename2, encounters, timeline1, timeline2, places1, places2 })
+    }
+
+    async fn save(&self, request: SaveRequest) -> Result<(), Error> {
+        let (record1, record2) = (&self.record(request.keys[0])?.record, &self.record(request.keys[1])?.record);
+        let [filename1, filename2] = request.filenames;
+        let session = Session {
+            summary: SessionSummary {
+                id: request.id,
+                saved_at: js_sys::Date::now(),
+                filenames: [filename1.clone(), filename2.clone()],
+                point_counts: [record1.points.len(), record2.points.len()],
+                encounter_count: request.output.encounters.len(),
+            },
+            files: [
+                StoredFile { filename: filename1, hash: request.hashes[0], record: record1.clone(), source_format: self.source_format(request.keys[0]) },
+                StoredFile { filename: filename2, hash: request.hashes[1], record: record2.clone(), source_format: self.source_format(request.keys[1]) },
+            ],
+            options: request.options,
+            filter: request.filter,
+            zones: request.zones,
+            preparation: request.preparation,
+            output: request.output,
+        };
+        Ok(save_session(&session).await?)
+    }
+}
+
+fn match_shard(matched: [Arc<CachedRecord>; 2], options: &EncounterOptions, shard: usize, shard_count: usize, reply: impl Fn(AnalysisReply)) -> Vec<Encounter> {
+    let [matched1, matched2] = matched;
+    let index = matched2.index();
+    reply(AnalysisReply::Indexed);
+    let points = time_shards(&matched1.record, shard_count).get(shard).map_or(&[][..], |range| &matched1.record.points[range.clone()]);
+    find_encounters_with_progress(&SpaceTimeRecord::new(points.to_vec(), Vec::new()), index, options, |done, total| {
+        reply(AnalysisReply::Matching(done as f64 / total.max(1) as f64))
+    })
+}
+
+#[worker(AnalysisWorker)]
+// Started through `POOL` below, which can terminate it
+#[allow(dead_code)]
+pub async fn analysis_worker(requests: leptos_workers::Receiver<TaggedRequest>, replies: Sender<TaggedReply>) {
+    let mut state = ResidentState { records: HashMap::new(), source_formats: HashMap::new(), prepared: Vec::with_capacity(MATCHED_CACHE_CAPACITY), matched: RecordCache::new(MATCHED_CACHE_CAPACITY) };
+    while let Ok((id, request)) = requests.recv_async().await {
+        let reply = |reply| {
+            let _ = replies.send((id, reply));
+        };
+        match request {
+            AnalysisRequest::Decode { key, file } => reply(AnalysisReply::Decoded(state.decode(key, file))),
+            AnalysisRequest::Keep { key, record, source_format } => state.keep(key, record, source_format),
+            AnalysisRequest::Forget { key } => state.forget(key),
+            AnalysisRequest::Fetch { key } => reply(AnalysisReply::Fetched(state.record(key).map(|cached| (cached.record.clone(), state.source_format(key))))),
+            AnalysisRequest::Match { keys, preparation, zones, options, shard, shard_count } => {
+                let matched = state.matched_pair(keys, &preparation, &zones);
+                reply(AnalysisReply::Matched(matched.map(|matched| match_shard(matched, &options, shard, shard_count, reply))))
+            },
+            AnalysisRequest::Summarize { keys, preparation, zones, options, encounters, filenames } => {
+                reply(AnalysisReply::Summarized(state.summarize(keys, &preparation, &zones, &options, encounters, filenames)))
+            },
+            AnalysisRequest::Save(request) => reply(AnalysisReply::Saved(state.save(*request).await)),
+        }
+    }
+}
+
+/// The page's end of a worker, routing each request's replies back to it
+#[derive(Clone)]
+pub struct Resident {
+    handle: AbortHandle<AnalysisWorker>,
+    requests: Sender<TaggedRequest>,
+    pending: Rc<RefCell<HashMap<u64, mpsc::UnboundedSender<AnalysisReply>>>>,
+    next_id: Rc<Cell<u64>>,
+}
+
+impl Resident {
+    fn start(pool: &PoolExecutor<AnalysisWorker>) -> Result<Self, Error> {
+        let (handle, requests, replies) = pool.channel().map_err(worker_error)?;
+        let pending: Rc<RefCell<HashMap<u64, mpsc::UnboundedSender<AnalysisReply>>>> = Rc::default();
+        let routes = pending.clone();
+        spawn_local(async move {
+            while let Ok((id, reply)) = replies.recv_async().await {
+                let mut routes = routes.borrow_mut();
+                let last = reply.is_last();
+                if let Some(route) = routes.get(&id) {
+                    let _ = route.unbounded_send(reply);
+                }
+                if last {
+                    routes.remove(&id);
+                }
+            }
+        });
+        Ok(Resident { handle, requests, pending, next_id: Rc::default() })
+    }
+
+    /// Send a request that has no reply
+    pub fn tell(&self, request: AnalysisRequest) {
+        let _ = self.requests.send((0, request));
+    }
+
+    /// Send a request and wait for its last reply, passing any before it to `progress`.
+    /// Fails if the worker is terminated before replying
+    pub async fn ask(&self, request: AnalysisRequest, progress: impl Fn(AnalysisReply)) -> Result<AnalysisReply, Error> {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        let (route, mut replies) = mpsc::unbounded();
+        self.pending.borrow_mut().insert(id, route);
+        let _ = self.requests.send((id, request));
+        while let Some(reply) = replies.next().await {
+            match reply.is_last() {
+                true => return Ok(reply),
+                false => progress(reply),
+            }
+        }
+        Err(Error::WebWorkerError("the analysis was cancelled".to_string()))
+    }
+}
+
+/// The workers and the records they all keep
+struct Residents {
+    workers: Vec<Resident>,
+    /// Least recently used first
+    keys: Vec<u64>,
+}
+
+thread_local! {
+    static POOL: Result<PoolExecutor<AnalysisWorker>, CreateWorkerError> = PoolExecutor::new(0);
+    static RESIDENTS: RefCell<Option<Residents>> = const { RefCell::new(None) };
+}
+
+fn worker_error(error: CreateWorkerError) -> Error {
+    Error::WebWorkerError(error.to_string())
+}
+
+/// The workers, one per core, started the first time they're needed
+pub fn residents() -> Result<Vec<Resident>, Error> {
+    RESIDENTS.with_borrow_mut(|residents| {
+        if let Some(residents) = residents {
+            return Ok(residents.workers.clone());
+        }
+        let count = (window().navigator().hardware_concurrency() as usize).clamp(1, MAX_WORKERS);
+        let workers = POOL.with(|pool| {
+            let pool = pool.as_ref().map_err(|error| worker_error(error.clone()))?;
+            (0..count).map(|_| Resident::start(pool)).collect::<Result<Vec<_>, _>>()
+        })?;
+        Ok(residents.insert(Residents { workers, keys: Vec::new() }).workers.clone())
+    })
+}
+
+/// Whether every worker keeps the record, counting it as used
+pub fn is_resident(key: u64) -> bool {
+    RESIDENTS.with_borrow_mut(|residents| {
+        let Some(residents) = residents else { return false };
+        let Some(position) = residents.keys.iter().position(|resident_key| *resident_key == key) else { return false };
+        let key = residents.keys.remove(position);
+        residents.keys.push(key);
+        true
+    })
+}
+
+/// Give the record to every worker but the one that decoded it, and have them all drop the least recently used
+/// record past `RECORD_CACHE_CAPACITY`
+pub fn keep_resident(key: u64, record: &SpaceTimeRecord, source_format: &str, decoded_by: Option<usize>) {
+    RESIDENTS.with_borrow_mut(|residents| {
+        let Some(residents) = residents else { return };
+        residents.keys.retain(|resident_key| *resident_key != key);
+        let forgotten = (residents.keys.len() >= RECORD_CACHE_CAPACITY).then(|| residents.keys.remove(0));
+        residents.keys.push(key);
+        for (index, worker) in residents.workers.iter().enumerate() {
+            if decoded_by != Some(index) {
+                worker.tell(AnalysisRequest::Keep { key, record: record.clone(), source_format: source_format.to_string() });
+            }
+            if let Some(key) = forgotten {
+                worker.tell(AnalysisRequest::Forget { key });
+            }
+        }
+    })
+}
+
+/// Stop every worker mid-request, their records go with them and fresh workers start on the next analysis
+pub fn terminate_residents() {
+    let Some(residents) = RESIDENTS.take() else { return };
+    for worker in residents.workers {
+        worker.handle.abort();
+        // Dropping the routes ends whatever is waiting on a reply
+        worker.pending.borrow_mut().clear();
+    }
+}